@@ -0,0 +1,200 @@
+//! Compares `CallbackFuture` against the previous implementation based on
+//! `Arc<Mutex<Option<T>>>` and `AtomicWaker`, and the boxed `CallbackFuture::new`
+//! against the unboxed `CallbackFuture::from_fn`. Heap allocations per iteration of each case
+//! are printed before the measurements.
+//!
+//! Run with `cargo bench --bench completion`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::RefCell;
+use std::hint::black_box;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use callback_future::{CallbackFuture, Completer};
+use criterion::{criterion_group, criterion_main, Criterion};
+use futures::Future;
+use futures::task::{Context, Poll, noop_waker_ref};
+
+use crate::mutex_future::MutexCallbackFuture;
+
+/// Previous implementation, kept for comparison
+mod mutex_future {
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+
+    use futures::Future;
+    use futures::task::{AtomicWaker, Context, Poll};
+
+    type Complete<T> = Box<dyn FnOnce(T) + Send + 'static>;
+    type Loader<T> = Box<dyn FnOnce(Complete<T>) + Send + 'static>;
+
+    struct Shared<T> {
+        result: Mutex<Option<T>>,
+        waker: AtomicWaker,
+    }
+
+    pub struct MutexCallbackFuture<T> {
+        loader: Option<Loader<T>>,
+        shared: Arc<Shared<T>>,
+    }
+
+    impl<T> MutexCallbackFuture<T> {
+        pub fn new(loader: impl FnOnce(Complete<T>) + Send + 'static) -> MutexCallbackFuture<T> {
+            MutexCallbackFuture {
+                loader: Some(Box::new(loader)),
+                shared: Arc::new(Shared { result: Mutex::new(None), waker: AtomicWaker::new() }),
+            }
+        }
+    }
+
+    impl<T: Send + 'static> Future for MutexCallbackFuture<T> {
+        type Output = T;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let self_mut = self.get_mut();
+            self_mut.shared.waker.register(cx.waker());
+            if let Some(loader) = self_mut.loader.take() {
+                let shared = self_mut.shared.clone();
+                loader(Box::new(move |value| {
+                    *shared.result.lock().unwrap() = Some(value);
+                    shared.waker.wake();
+                }));
+            }
+            match self_mut.shared.result.lock().unwrap().take() {
+                Some(value) => Poll::Ready(value),
+                None => Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Counts heap allocations of the whole process
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const ALLOCATION_ITERATIONS: usize = 10_000;
+
+type Complete = Box<dyn FnOnce(u64) + Send>;
+
+thread_local! {
+    static PENDING: RefCell<Option<Complete>> = RefCell::new(None);
+    static PENDING_COMPLETER: RefCell<Option<Completer<u64>>> = const { RefCell::new(None) };
+}
+
+fn poll<F: Future + Unpin>(future: &mut F) -> Poll<F::Output> {
+    Pin::new(future).poll(&mut Context::from_waker(noop_waker_ref()))
+}
+
+/// Loader completes synchronously: a single poll returns the value
+fn complete_sync<F: Future<Output = u64> + Unpin>(future: F) {
+    let mut future = black_box(future);
+    assert_eq!(poll(&mut future), Poll::Ready(42));
+}
+
+/// Loader stores completer: first poll is pending, then callback completes the future
+fn complete_later<F: Future<Output = u64> + Unpin>(future: F) {
+    let mut future = black_box(future);
+    assert!(poll(&mut future).is_pending());
+    PENDING.with(|pending| pending.borrow_mut().take().unwrap()(42));
+    assert_eq!(poll(&mut future), Poll::Ready(42));
+}
+
+fn store_pending(complete: Complete) {
+    PENDING.with(|pending| *pending.borrow_mut() = Some(complete));
+}
+
+/// Same as `complete_later`, for futures created with `CallbackFuture::from_fn`
+fn complete_later_unboxed<F: Future<Output = u64> + Unpin>(future: F) {
+    let mut future = black_box(future);
+    assert!(poll(&mut future).is_pending());
+    PENDING_COMPLETER.with(|pending| pending.borrow_mut().take().unwrap().complete(42));
+    assert_eq!(poll(&mut future), Poll::Ready(42));
+}
+
+fn store_pending_completer(completer: Completer<u64>) {
+    PENDING_COMPLETER.with(|pending| *pending.borrow_mut() = Some(completer));
+}
+
+/// Benchmarked scenario with its cases
+type Group = (&'static str, Vec<(&'static str, fn())>);
+
+fn cases() -> Vec<Group> {
+    vec![
+        ("sync completion", vec![
+            ("CallbackFuture", || complete_sync(CallbackFuture::new(|complete| complete(42)))),
+            ("CallbackFuture::from_fn", || {
+                complete_sync(CallbackFuture::from_fn(|completer| completer.complete(42)))
+            }),
+            ("MutexCallbackFuture", || complete_sync(MutexCallbackFuture::new(|complete| complete(42)))),
+        ]),
+        // real loaders capture state, so that boxing them allocates
+        ("sync completion, capturing loader", vec![
+            ("CallbackFuture", || {
+                let value = black_box(42);
+                complete_sync(CallbackFuture::new(move |complete| complete(value)))
+            }),
+            ("CallbackFuture::from_fn", || {
+                let value = black_box(42);
+                complete_sync(CallbackFuture::from_fn(move |completer| completer.complete(value)))
+            }),
+            ("MutexCallbackFuture", || {
+                let value = black_box(42);
+                complete_sync(MutexCallbackFuture::new(move |complete| complete(value)))
+            }),
+        ]),
+        ("later completion", vec![
+            ("CallbackFuture", || complete_later(CallbackFuture::new(store_pending))),
+            ("CallbackFuture::from_fn", || {
+                complete_later_unboxed(CallbackFuture::from_fn(store_pending_completer))
+            }),
+            ("MutexCallbackFuture", || complete_later(MutexCallbackFuture::new(store_pending))),
+        ]),
+        ("ready", vec![
+            ("CallbackFuture", || complete_sync(CallbackFuture::ready(42))),
+        ]),
+    ]
+}
+
+fn allocations(_: &mut Criterion) {
+    for (group, cases) in cases() {
+        for (name, case) in cases {
+            case();
+            let before = ALLOCATIONS.load(Ordering::Relaxed);
+            for _ in 0..ALLOCATION_ITERATIONS {
+                case();
+            }
+            let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+            println!("{:<56} {:>6.1} allocs/iter", format!("{}/{}", group, name),
+                     allocations as f64 / ALLOCATION_ITERATIONS as f64);
+        }
+    }
+}
+
+fn completion(c: &mut Criterion) {
+    for (group, cases) in cases() {
+        let mut group = c.benchmark_group(group);
+        for (name, case) in cases {
+            group.bench_function(name, |b| b.iter(case));
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, allocations, completion);
+criterion_main!(benches);
@@ -0,0 +1,302 @@
+//! Procedural macros of `callback-future`, re-exported by it with the `macros` feature.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{
+    Attribute, Block, Error, FnArg, GenericArgument, Ident, Pat, PatType, PathArguments, Receiver,
+    Result, ReturnType, Signature, Token, Type, TypeParamBound, Visibility,
+};
+
+/// Generates an async sibling of a function taking a completion callback as its last parameter.
+///
+/// The callback must be an `impl FnOnce(..)` or a `Box<dyn FnOnce(..)>`. The sibling, named
+/// after the function with an `_async` suffix unless renamed with `#[asyncify(name = ..)]`,
+/// takes the remaining parameters and returns a `CallbackFuture` resolving to the argument
+/// of the callback: `()` for a callback without arguments, a tuple for several arguments.
+///
+/// A function without a `self` receiver is called by the loader of the future, upon first poll,
+/// so its parameters must be `'static`. A method is called right away by its sibling, and
+/// the returned future is already started; in a trait, the sibling is a provided method.
+/// Associated functions without a receiver, and methods of trait implementations,
+/// are not supported.
+///
+/// # Examples
+/// ```
+/// use callback_future::asyncify;
+/// use futures::executor::block_on;
+/// use std::thread;
+///
+/// #[asyncify]
+/// fn fetch(id: u32, callback: impl FnOnce(String) + Send + 'static) {
+///     thread::spawn(move || callback(format!("item {}", id)));
+/// }
+///
+/// trait Store {
+///     #[asyncify(name = get)]
+///     fn get_with(&self, key: &str, callback: Box<dyn FnOnce(Option<u32>, usize) + Send>);
+/// }
+///
+/// struct Empty;
+///
+/// impl Store for Empty {
+///     fn get_with(&self, _key: &str, callback: Box<dyn FnOnce(Option<u32>, usize) + Send>) {
+///         callback(None, 0);
+///     }
+/// }
+///
+/// assert_eq!(block_on(fetch_async(42)), "item 42");
+/// assert_eq!(block_on(Empty.get("key")), (None, 0));
+/// ```
+#[proc_macro_attribute]
+pub fn asyncify(args: TokenStream, item: TokenStream) -> TokenStream {
+    let original = TokenStream2::from(item.clone());
+    // the function is kept as is, so that an error does not cascade to its callers
+    let sibling = syn::parse::<Args>(args)
+        .and_then(|args| expand(args, syn::parse(item)?))
+        .unwrap_or_else(Error::into_compile_error);
+    quote!(#original #sibling).into()
+}
+
+/// Arguments of the attribute: `name = ident`
+struct Args {
+    name: Option<Ident>,
+}
+
+impl Parse for Args {
+    fn parse(input: ParseStream) -> Result<Args> {
+        let mut name = None;
+        while !input.is_empty() {
+            let key: Ident = input.parse()?;
+            if key != "name" {
+                return Err(Error::new(key.span(), "unknown argument, expected `name = ..`"));
+            }
+            if name.is_some() {
+                return Err(Error::new(key.span(), "duplicate `name` argument"));
+            }
+            input.parse::<Token![=]>()?;
+            name = Some(input.parse()?);
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        Ok(Args { name })
+    }
+}
+
+/// A function, a method, or a trait method without a body
+struct Function {
+    attrs: Vec<Attribute>,
+    vis: Visibility,
+    sig: Signature,
+}
+
+impl Parse for Function {
+    fn parse(input: ParseStream) -> Result<Function> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let vis = input.parse()?;
+        let sig = input.parse()?;
+        if input.peek(Token![;]) {
+            input.parse::<Token![;]>()?;
+        } else {
+            input.parse::<Block>()?;
+        }
+        Ok(Function { attrs, vis, sig })
+    }
+}
+
+/// The completion callback, with the types of its arguments
+struct Callback {
+    boxed: bool,
+    args: Vec<Type>,
+}
+
+fn expand(args: Args, function: Function) -> Result<TokenStream2> {
+    let Function { attrs, vis, sig } = function;
+    if let Some(asyncness) = sig.asyncness {
+        return Err(Error::new(asyncness.span, "`asyncify` cannot be applied to an `async fn`"));
+    }
+    if let ReturnType::Type(_, output) = &sig.output {
+        if !is_unit(output) {
+            return Err(Error::new_spanned(output, "the function must not return a value, \
+                                                   its result is passed to the callback"));
+        }
+    }
+
+    let mut inputs = sig.inputs.iter().collect::<Vec<_>>();
+    let callback = match inputs.pop() {
+        Some(FnArg::Typed(PatType { ty, .. })) => parse_callback(ty)?,
+        _ => return Err(Error::new(sig.paren_token.span.join(), "expected a trailing callback \
+                                                                 parameter of type `impl FnOnce(T)`")),
+    };
+
+    let mut receiver = None;
+    let mut names = Vec::new();
+    let mut types = Vec::new();
+    for input in inputs {
+        match input {
+            FnArg::Receiver(input) => receiver = Some(strip_mut(input)),
+            FnArg::Typed(PatType { pat, ty, .. }) => {
+                let name = match &**pat {
+                    Pat::Ident(pat) if pat.by_ref.is_none() && pat.subpat.is_none() => &pat.ident,
+                    _ => return Err(Error::new_spanned(pat, "expected a parameter name, \
+                                                             patterns are not supported")),
+                };
+                if receiver.is_none() && is_borrowed(ty) {
+                    return Err(Error::new_spanned(ty, "borrowed parameters are only supported \
+                                                       by methods, as the loader is called later"));
+                }
+                names.push(name);
+                types.push(&**ty);
+            }
+        }
+    }
+
+    let ident = &sig.ident;
+    let name = args.name.unwrap_or_else(|| format_ident!("{}_async", ident));
+    let doc = match receiver {
+        Some(_) => format!("Async version of [`{0}`](Self::{0})", ident),
+        None => format!("Async version of [`{}`]", ident),
+    };
+    let cfgs = attrs.iter().filter(|attr| attr.path().is_ident("cfg"));
+    let unsafety = &sig.unsafety;
+    let generics = &sig.generics;
+    let where_clause = &sig.generics.where_clause;
+
+    let output = match callback.args.as_slice() {
+        [] => quote!(()),
+        [arg] => quote!(#arg),
+        args => quote!((#(#args),*)),
+    };
+    let values = (0..callback.args.len())
+        .map(|i| format_ident!("value{}", i, span = Span::mixed_site()))
+        .collect::<Vec<_>>();
+    let value = match values.as_slice() {
+        [value] => quote!(#value),
+        values => quote!((#(#values),*)),
+    };
+
+    // locals of the generated code do not clash with parameter names
+    let complete = Ident::new("complete", Span::mixed_site());
+    let completer = Ident::new("completer", Span::mixed_site());
+    let future = Ident::new("future", Span::mixed_site());
+    let completion = match receiver {
+        Some(_) => quote!(#completer.complete(#value)),
+        None => quote!(#complete(#value)),
+    };
+    let arg_types = &callback.args;
+    let mut argument = quote!(move |#(#values: #arg_types),*| #completion);
+    if callback.boxed {
+        argument = quote!(::std::boxed::Box::new(#argument));
+    }
+    let call = match receiver {
+        Some(_) => quote!(self.#ident(#(#names,)* #argument)),
+        None => quote!(#ident(#(#names,)* #argument)),
+    };
+    let call = match unsafety {
+        Some(_) => quote!(unsafe { #call }),
+        None => call,
+    };
+
+    let body = match receiver {
+        Some(_) => quote! {
+            let (#future, #completer) = ::callback_future::CallbackFuture::channel();
+            #call;
+            #future
+        },
+        None => quote! {
+            ::callback_future::CallbackFuture::new(move |#complete| {
+                #call;
+            })
+        },
+    };
+    let receiver = receiver.map(|receiver| quote!(#receiver,));
+
+    Ok(quote! {
+        #(#cfgs)*
+        #[doc = #doc]
+        #vis #unsafety fn #name #generics (#receiver #(#names: #types),*)
+            -> ::callback_future::CallbackFuture<#output> #where_clause {
+            #body
+        }
+    })
+}
+
+fn parse_callback(ty: &Type) -> Result<Callback> {
+    match ty {
+        Type::ImplTrait(ty) => Ok(Callback { boxed: false, args: fn_once_args(&ty.bounds, ty)? }),
+        Type::Path(path) if path.qself.is_none() => {
+            let segment = path.path.segments.last().unwrap();
+            if let PathArguments::AngleBracketed(args) = &segment.arguments {
+                if let (true, Some(GenericArgument::Type(Type::TraitObject(object)))) =
+                    (segment.ident == "Box" && args.args.len() == 1, args.args.first()) {
+                    return Ok(Callback { boxed: true, args: fn_once_args(&object.bounds, ty)? });
+                }
+            }
+            Err(expected_callback(ty))
+        }
+        _ => Err(expected_callback(ty)),
+    }
+}
+
+/// Returns the arguments of the `FnOnce` bound
+fn fn_once_args(bounds: &Punctuated<TypeParamBound, Token![+]>, ty: impl quote::ToTokens)
+                -> Result<Vec<Type>> {
+    for bound in bounds {
+        let segment = match bound {
+            TypeParamBound::Trait(bound) => bound.path.segments.last().unwrap(),
+            _ => continue,
+        };
+        if segment.ident == "Fn" || segment.ident == "FnMut" {
+            return Err(Error::new(segment.ident.span(), "expected an `FnOnce` callback, \
+                                                         as the future is completed once"));
+        }
+        if segment.ident != "FnOnce" {
+            continue;
+        }
+        let args = match &segment.arguments {
+            PathArguments::Parenthesized(args) => args,
+            _ => break,
+        };
+        if let ReturnType::Type(_, output) = &args.output {
+            if !is_unit(output) {
+                return Err(Error::new_spanned(output, "the callback must not return a value"));
+            }
+        }
+        if let Some(arg) = args.inputs.iter().find(|arg| is_borrowed(arg)) {
+            return Err(Error::new_spanned(arg, "borrowed callback arguments are not supported, \
+                                               as the future outlives the callback"));
+        }
+        return Ok(args.inputs.iter().cloned().collect());
+    }
+    Err(expected_callback(ty))
+}
+
+fn expected_callback(ty: impl quote::ToTokens) -> Error {
+    Error::new_spanned(ty, "expected a callback of type `impl FnOnce(T)` or `Box<dyn FnOnce(T)>`")
+}
+
+fn is_unit(ty: &Type) -> bool {
+    matches!(ty, Type::Tuple(tuple) if tuple.elems.is_empty())
+}
+
+/// Returns `true` for references, unless they are `'static`
+fn is_borrowed(ty: &Type) -> bool {
+    match ty {
+        Type::Reference(reference) => {
+            !matches!(&reference.lifetime, Some(lifetime) if lifetime.ident == "static")
+        }
+        _ => false,
+    }
+}
+
+/// Drops `mut` from a `mut self` receiver, which the sibling does not need
+fn strip_mut(receiver: &Receiver) -> Receiver {
+    let mut receiver = receiver.clone();
+    if receiver.reference.is_none() {
+        receiver.mutability = None;
+    }
+    receiver
+}
@@ -0,0 +1,170 @@
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::thread;
+
+use futures::executor::block_on;
+use futures::poll;
+
+use callback_future::{asyncify, CallbackFuture};
+
+#[asyncify]
+fn fetch(id: u32, callback: impl FnOnce(String) + Send + 'static) {
+    thread::spawn(move || callback(format!("item {}", id)));
+}
+
+#[asyncify]
+fn flush(callback: impl FnOnce() + Send + 'static) {
+    callback();
+}
+
+#[asyncify]
+fn read(complete: u8, future: u16, callback: Box<dyn FnOnce(i32, Vec<u8>) + Send>) {
+    // parameters named like the locals of the generated code
+    callback(i32::from(complete) + i32::from(future), vec![1, 2, 3]);
+}
+
+#[asyncify(name = lookup)]
+fn lookup_with<K: ToString + Send + 'static>(key: K, callback: impl FnOnce(Option<String>)) {
+    callback(Some(key.to_string()));
+}
+
+#[asyncify]
+unsafe fn read_raw(pointer: usize, callback: impl FnOnce(usize) + Send + 'static) {
+    callback(pointer);
+}
+
+mod api {
+    use callback_future::asyncify;
+
+    #[asyncify]
+    pub fn ping(callback: impl FnOnce(&'static str)) {
+        callback("pong");
+    }
+}
+
+struct Device {
+    requests: Mutex<Vec<Box<dyn FnOnce() + Send>>>,
+}
+
+impl Device {
+    #[asyncify]
+    fn request(&self, command: &str, callback: impl FnOnce(Result<u32, String>) + Send + 'static) {
+        let result = match command {
+            "read" => Ok(42),
+            _ => Err(command.to_string()),
+        };
+        self.requests.lock().unwrap().push(Box::new(move || callback(result)));
+    }
+
+    fn respond(&self) {
+        for request in self.requests.lock().unwrap().drain(..) {
+            request();
+        }
+    }
+}
+
+trait Store {
+    #[asyncify(name = get)]
+    fn get_with(&self, key: &str, callback: impl FnOnce(Option<u32>) + Send + 'static);
+
+    #[asyncify]
+    fn clear(&mut self, callback: Box<dyn FnOnce() + Send>);
+}
+
+struct MemoryStore(Vec<(String, u32)>);
+
+impl Store for MemoryStore {
+    fn get_with(&self, key: &str, callback: impl FnOnce(Option<u32>) + Send + 'static) {
+        callback(self.0.iter().find(|(k, _)| k == key).map(|(_, value)| *value));
+    }
+
+    fn clear(&mut self, callback: Box<dyn FnOnce() + Send>) {
+        self.0.clear();
+        callback();
+    }
+}
+
+#[test]
+fn test_function() {
+    let fu: CallbackFuture<String> = fetch_async(42);
+
+    assert_eq!(block_on(fu), "item 42");
+}
+
+#[test]
+fn test_function_called_on_poll() {
+    let (tx, rx) = mpsc::channel();
+
+    #[asyncify]
+    fn notify(tx: mpsc::Sender<()>, callback: impl FnOnce() + Send + 'static) {
+        tx.send(()).unwrap();
+        callback();
+    }
+
+    let mut fu = notify_async(tx);
+    assert!(!fu.is_started());
+    assert!(rx.try_recv().is_err());
+    assert!(block_on(async { poll!(&mut fu) }).is_ready());
+    assert!(rx.try_recv().is_ok());
+}
+
+#[test]
+fn test_no_arguments() {
+    block_on(flush_async());
+}
+
+#[test]
+fn test_several_arguments_boxed() {
+    assert_eq!(block_on(read_async(1, 2)), (3, vec![1, 2, 3]));
+}
+
+#[test]
+fn test_renamed_generic() {
+    assert_eq!(block_on(lookup(42)), Some("42".to_string()));
+}
+
+#[test]
+fn test_unsafe() {
+    let fu = unsafe { read_raw_async(42) };
+
+    assert_eq!(block_on(fu), 42);
+}
+
+#[test]
+fn test_visibility() {
+    assert_eq!(block_on(api::ping_async()), "pong");
+}
+
+#[test]
+fn test_method_started_on_call() {
+    let device = Device { requests: Mutex::new(Vec::new()) };
+    let command = String::from("read");
+
+    // the argument is borrowed, so the method is called right away
+    let fu = device.request_async(&command);
+    drop(command);
+    assert!(fu.is_started());
+    assert!(!fu.is_completed());
+    device.respond();
+    assert_eq!(block_on(fu), Ok(42));
+
+    let fu = device.request_async("write");
+    device.respond();
+    assert_eq!(block_on(fu), Err("write".to_string()));
+}
+
+#[test]
+fn test_trait_method() {
+    let mut store = MemoryStore(vec![("answer".to_string(), 42)]);
+
+    assert_eq!(block_on(store.get("answer")), Some(42));
+    assert_eq!(block_on(store.get("question")), None);
+    block_on(store.clear_async());
+    assert_eq!(block_on(store.get("answer")), None);
+}
+
+#[test]
+fn test_diagnostics() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
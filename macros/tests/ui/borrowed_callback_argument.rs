@@ -0,0 +1,6 @@
+use callback_future::asyncify;
+
+#[asyncify]
+fn read(_callback: impl FnOnce(i32, &[u8]) + Send + 'static) {}
+
+fn main() {}
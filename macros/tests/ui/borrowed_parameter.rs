@@ -0,0 +1,6 @@
+use callback_future::asyncify;
+
+#[asyncify]
+fn fetch(_key: &str, _callback: impl FnOnce(String) + Send + 'static) {}
+
+fn main() {}
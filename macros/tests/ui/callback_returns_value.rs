@@ -0,0 +1,6 @@
+use callback_future::asyncify;
+
+#[asyncify]
+fn fetch(_callback: impl FnOnce(String) -> bool + Send + 'static) {}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use callback_future::asyncify;
+
+#[asyncify]
+fn subscribe(_callback: impl FnMut(u32) + Send + 'static) {}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use callback_future::asyncify;
+
+#[asyncify]
+fn flush() {}
+
+fn main() {}
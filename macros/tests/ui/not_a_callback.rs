@@ -0,0 +1,6 @@
+use callback_future::asyncify;
+
+#[asyncify]
+fn fetch(_id: u32, _callback: fn(String)) {}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use callback_future::asyncify;
+
+#[asyncify]
+struct Request;
+
+fn main() {}
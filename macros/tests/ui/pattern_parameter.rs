@@ -0,0 +1,6 @@
+use callback_future::asyncify;
+
+#[asyncify]
+fn fetch((_x, _y): (u32, u32), _callback: impl FnOnce(u32) + Send + 'static) {}
+
+fn main() {}
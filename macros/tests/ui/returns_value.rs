@@ -0,0 +1,8 @@
+use callback_future::asyncify;
+
+#[asyncify]
+fn fetch(_callback: impl FnOnce(String) + Send + 'static) -> bool {
+    true
+}
+
+fn main() {}
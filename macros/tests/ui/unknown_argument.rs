@@ -0,0 +1,6 @@
+use callback_future::asyncify;
+
+#[asyncify(rename = get)]
+fn fetch(_callback: impl FnOnce(String) + Send + 'static) {}
+
+fn main() {}
@@ -0,0 +1,53 @@
+//! Helpers for smol and async-std, enabled by the `async-io` feature.
+//!
+//! Built on the `blocking` thread pool and the `async-io` reactor shared by both runtimes,
+//! so nothing here depends on which executor polls the futures.
+
+use std::time::Duration;
+
+use crate::{CallbackFuture, Canceled, Sleep, Timer};
+
+/// Runs a blocking function on the `blocking` thread pool
+///
+/// The function is spawned upon first poll. Resolves to `Err(Canceled)` if the function panics.
+///
+/// # Examples
+/// ```
+/// use callback_future::async_io::unblock;
+///
+/// smol::block_on(async {
+///     let len = unblock(|| std::fs::read("Cargo.toml").map(|bytes| bytes.len()));
+///     assert!(len.await.unwrap().unwrap() > 0);
+/// });
+/// ```
+pub fn unblock<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static)
+                                  -> CallbackFuture<Result<T, Canceled>> {
+    CallbackFuture::try_new(move |complete| {
+        // a panic drops the completion callback, completing with `Err(Canceled)`
+        blocking::unblock(move || complete(f())).detach();
+    })
+}
+
+/// Timer backed by `async_io::Timer`, for `CallbackFuture::with_timeout_on` and `retry_on`.
+///
+/// # Examples
+/// ```
+/// use callback_future::{CallbackFuture, Elapsed};
+/// use callback_future::async_io::AsyncIoTimer;
+/// use std::time::Duration;
+///
+/// let future = CallbackFuture::<()>::new(|complete| std::mem::forget(complete));
+/// let result = smol::block_on(future.with_timeout_on(&AsyncIoTimer, Duration::from_millis(10)));
+/// assert_eq!(result, Err(Elapsed));
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AsyncIoTimer;
+
+impl Timer for AsyncIoTimer {
+    fn sleep(&self, duration: Duration) -> Sleep {
+        let timer = ::async_io::Timer::after(duration);
+        Box::pin(async move {
+            timer.await;
+        })
+    }
+}
@@ -0,0 +1,457 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::SharedCallbackFuture;
+
+/// Eviction policy of a CallbackCache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CachePolicy {
+    /// Completed entries expire after this time since completion
+    pub ttl: Option<Duration>,
+    /// Maximum number of entries; the least recently used entry is evicted when exceeded
+    pub capacity: Option<usize>,
+}
+
+type Filter<V> = Box<dyn Fn(&V) -> bool + Send + Sync + 'static>;
+
+struct Entry<V> {
+    future: SharedCallbackFuture<V>,
+    id: u64,
+    expires_at: Option<Instant>,
+    last_used: u64,
+}
+
+struct CacheState<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    by_use: BTreeMap<u64, K>, // LRU index: last use to key
+    by_expiry: BTreeMap<(Instant, u64), K>, // completed entries with a TTL: expiry and id to key
+    ticks: u64, // source of entry ids and LRU timestamps
+}
+
+impl<K: Hash + Eq + Clone, V> CacheState<K, V> {
+    fn insert(&mut self, key: K, entry: Entry<V>) {
+        self.remove(&key);
+        self.by_use.insert(entry.last_used, key.clone());
+        self.entries.insert(key, entry);
+    }
+
+    fn remove(&mut self, key: &K) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.by_use.remove(&entry.last_used);
+            if let Some(expires_at) = entry.expires_at {
+                self.by_expiry.remove(&(expires_at, entry.id));
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.by_use.clear();
+        self.by_expiry.clear();
+    }
+
+    /// Marks the entry as used, returning its future
+    fn touch(&mut self, key: &K, tick: u64) -> Option<SharedCallbackFuture<V>> {
+        let entry = self.entries.get_mut(key)?;
+        if let Some(key) = self.by_use.remove(&entry.last_used) {
+            self.by_use.insert(tick, key);
+        }
+        entry.last_used = tick;
+        Some(entry.future.clone())
+    }
+
+    /// Removes entries whose TTL has passed
+    fn purge_expired(&mut self, now: Instant) {
+        while let Some(entry) = self.by_expiry.first_entry() {
+            if entry.key().0 > now {
+                break;
+            }
+            let key = entry.remove();
+            self.remove(&key);
+        }
+    }
+
+    fn evict_lru(&mut self) {
+        if let Some((_, key)) = self.by_use.pop_first() {
+            self.remove(&key);
+        }
+    }
+}
+
+struct CacheInner<K, V> {
+    state: Mutex<CacheState<K, V>>,
+    policy: CachePolicy,
+    filter: Filter<V>,
+}
+
+/// A memoizing cache of callback results.
+///
+/// `get` shares one in-flight callback operation between all callers of the same key
+/// and keeps its result according to the `CachePolicy`. Cloning the cache shares it.
+pub struct CallbackCache<K, V> {
+    inner: Arc<CacheInner<K, V>>,
+}
+
+impl<K, V> Clone for CallbackCache<K, V> {
+    fn clone(&self) -> CallbackCache<K, V> {
+        CallbackCache { inner: self.inner.clone() }
+    }
+}
+
+impl<K: Hash + Eq + Clone + Send + 'static, V: Clone + Send + 'static> Default for CallbackCache<K, V> {
+    fn default() -> CallbackCache<K, V> {
+        CallbackCache::new()
+    }
+}
+
+impl<K: Hash + Eq + Clone + Send + 'static, V: Clone + Send + 'static> CallbackCache<K, V> {
+    /// Creates a new CallbackCache which keeps all results forever
+    pub fn new() -> CallbackCache<K, V> {
+        CallbackCache::with_policy(CachePolicy::default())
+    }
+
+    /// Creates a new CallbackCache with the given eviction policy
+    ///
+    /// # Panics
+    /// Panics if policy capacity is zero.
+    pub fn with_policy(policy: CachePolicy) -> CallbackCache<K, V> {
+        CallbackCache::with_filter(policy, |_| true)
+    }
+
+    /// Creates a new CallbackCache which keeps only results accepted by `filter`
+    ///
+    /// Rejected results are still delivered to all awaiters of the in-flight operation,
+    /// but the next `get` of the key invokes its loader again.
+    ///
+    /// # Panics
+    /// Panics if policy capacity is zero.
+    pub fn with_filter(policy: CachePolicy, filter: impl Fn(&V) -> bool + Send + Sync + 'static)
+                       -> CallbackCache<K, V> {
+        assert!(policy.capacity != Some(0), "cache capacity must be positive");
+        CallbackCache {
+            inner: Arc::new(CacheInner {
+                state: Mutex::new(CacheState {
+                    entries: HashMap::new(),
+                    by_use: BTreeMap::new(),
+                    by_expiry: BTreeMap::new(),
+                    ticks: 0,
+                }),
+                policy,
+                filter: Box::new(filter),
+            }),
+        }
+    }
+
+    /// Returns a future resolving to the cached or in-flight result for the key
+    ///
+    /// If there is neither, a new operation is started with the given loader upon first poll.
+    ///
+    /// # Examples
+    /// ```
+    /// use callback_future::CallbackCache;
+    /// use futures::executor::block_on;
+    /// use std::thread;
+    ///
+    /// fn resolve(cache: &CallbackCache<String, u32>, host: &str) -> u32 {
+    ///     let host_name = host.to_string();
+    ///     block_on(cache.get(host.to_string(), move |complete| {
+    ///         // the lookup is made once per host
+    ///         thread::spawn(move || complete(host_name.len() as u32));
+    ///     }))
+    /// }
+    ///
+    /// let cache = CallbackCache::new();
+    /// assert_eq!(resolve(&cache, "example.com"), 11);
+    /// assert_eq!(resolve(&cache, "example.com"), 11);
+    /// ```
+    pub fn get(&self, key: K, loader: impl FnOnce(Box<dyn FnOnce(V) + Send + 'static>) + Send + 'static)
+               -> SharedCallbackFuture<V> {
+        let mut state = self.inner.state.lock().unwrap();
+        state.ticks += 1;
+        let tick = state.ticks;
+        state.purge_expired(Instant::now());
+        if let Some(future) = state.touch(&key, tick) {
+            return future;
+        }
+
+        let inner = Arc::downgrade(&self.inner);
+        let entry_key = key.clone();
+        let future = SharedCallbackFuture::new(move |complete: Box<dyn FnOnce(V) + Send>| {
+            loader(Box::new(move |value| {
+                // update the entry before waking awaiters, unless it was evicted or invalidated
+                if let Some(inner) = inner.upgrade() {
+                    inner.complete(&entry_key, tick, &value);
+                }
+                complete(value);
+            }));
+        });
+        state.insert(key, Entry {
+            future: future.clone(),
+            id: tick,
+            expires_at: None,
+            last_used: tick,
+        });
+        if let Some(capacity) = self.inner.policy.capacity {
+            if state.entries.len() > capacity {
+                state.evict_lru();
+            }
+        }
+        future
+    }
+
+    /// Removes the entry for the key; its in-flight operation still completes for its awaiters
+    pub fn invalidate(&self, key: &K) {
+        self.inner.state.lock().unwrap().remove(key);
+    }
+
+    /// Removes all entries
+    pub fn invalidate_all(&self) {
+        self.inner.state.lock().unwrap().clear();
+    }
+
+    /// Returns the number of cached and in-flight entries, removing expired ones
+    pub fn len(&self) -> usize {
+        let mut state = self.inner.state.lock().unwrap();
+        state.purge_expired(Instant::now());
+        state.entries.len()
+    }
+
+    /// Returns `true` if there are no entries
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K: Hash + Eq + Clone + Send + 'static, T: Clone + Send + 'static, E: Clone + Send + 'static>
+CallbackCache<K, Result<T, E>> {
+    /// Creates a new CallbackCache which doesn't keep failed results
+    ///
+    /// # Panics
+    /// Panics if policy capacity is zero.
+    pub fn without_failures(policy: CachePolicy) -> CallbackCache<K, Result<T, E>> {
+        CallbackCache::with_filter(policy, Result::is_ok)
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> CacheInner<K, V> {
+    fn complete(&self, key: &K, id: u64, value: &V) {
+        let keep = (self.filter)(value);
+        let mut state = self.state.lock().unwrap();
+        match state.entries.get_mut(key) {
+            Some(entry) if entry.id == id => {
+                if !keep {
+                    state.remove(key);
+                } else if let Some(ttl) = self.policy.ttl {
+                    // an expiry too far to represent is never reached: the entry doesn't expire
+                    if let Some(expires_at) = Instant::now().checked_add(ttl) {
+                        entry.expires_at = Some(expires_at);
+                        state.by_expiry.insert((expires_at, id), key.clone());
+                    }
+                }
+            }
+            _ => {} // entry was evicted or replaced
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use futures::executor::block_on;
+    use futures::join;
+
+    use crate::{CachePolicy, CallbackCache};
+
+    /// Loader which counts its invocations and completes with `value` from another thread
+    fn counting_loader<V: Send + 'static>(loads: &Arc<AtomicUsize>, value: V)
+                                          -> impl FnOnce(Box<dyn FnOnce(V) + Send>) + Send + 'static {
+        let loads = loads.clone();
+        move |complete| {
+            loads.fetch_add(1, Ordering::SeqCst);
+            thread::spawn(move || complete(value));
+        }
+    }
+
+    #[test]
+    fn test_memoize() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let cache = CallbackCache::new();
+
+        assert_eq!(block_on(cache.get("a", counting_loader(&loads, 1))), 1);
+        assert_eq!(block_on(cache.get("a", counting_loader(&loads, 2))), 1);
+        assert_eq!(block_on(cache.get("b", counting_loader(&loads, 3))), 3);
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_share_in_flight() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let (tx, rx) = mpsc::channel();
+        let cache = CallbackCache::new();
+        let loader_loads = loads.clone();
+        let fu1 = cache.get("a", move |complete| {
+            loader_loads.fetch_add(1, Ordering::SeqCst);
+            tx.send(complete).unwrap();
+        });
+        let fu2 = cache.get("a", counting_loader(&loads, 2));
+
+        let waiter = thread::spawn(move || block_on(async { join!(fu1, fu2) }));
+        rx.recv().unwrap()(1);
+
+        assert_eq!(waiter.join().unwrap(), (1, 1));
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_ttl() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let cache = CallbackCache::with_policy(CachePolicy { ttl: Some(Duration::from_millis(50)), capacity: None });
+
+        assert_eq!(block_on(cache.get("a", counting_loader(&loads, 1))), 1);
+        assert_eq!(block_on(cache.get("a", counting_loader(&loads, 2))), 1);
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(block_on(cache.get("a", counting_loader(&loads, 3))), 3);
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_ttl_max() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let cache = CallbackCache::with_policy(CachePolicy { ttl: Some(Duration::MAX), capacity: None });
+
+        assert_eq!(block_on(cache.get("a", counting_loader(&loads, 1))), 1);
+        assert_eq!(block_on(cache.get("a", counting_loader(&loads, 2))), 1);
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert!(cache.inner.state.lock().unwrap().by_expiry.is_empty());
+    }
+
+    #[test]
+    fn test_ttl_evicts_other_keys() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let cache = CallbackCache::with_policy(CachePolicy { ttl: Some(Duration::from_millis(50)), capacity: None });
+
+        for key in 0..10 {
+            assert_eq!(block_on(cache.get(key, counting_loader(&loads, key))), key);
+        }
+        assert_eq!(cache.len(), 10);
+        thread::sleep(Duration::from_millis(100));
+        // expired entries are purged by a `get` of any key
+        assert_eq!(block_on(cache.get(10, counting_loader(&loads, 10))), 10);
+        assert_eq!(cache.inner.state.lock().unwrap().entries.len(), 1);
+        // and are not counted
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn test_ttl_in_flight() {
+        let (tx, rx) = mpsc::channel();
+        let loads = Arc::new(AtomicUsize::new(0));
+        let cache = CallbackCache::with_policy(CachePolicy { ttl: Some(Duration::from_millis(10)), capacity: None });
+        let fu1 = cache.get("a", move |complete| tx.send(complete).unwrap());
+
+        let waiter = thread::spawn(move || block_on(fu1));
+        thread::sleep(Duration::from_millis(50));
+        // in-flight entry doesn't expire
+        let fu2 = cache.get("a", counting_loader(&loads, 2));
+        rx.recv().unwrap()(1);
+
+        assert_eq!(waiter.join().unwrap(), 1);
+        assert_eq!(block_on(fu2), 1);
+        assert_eq!(loads.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_lru() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let cache = CallbackCache::with_policy(CachePolicy { ttl: None, capacity: Some(2) });
+
+        assert_eq!(block_on(cache.get("a", counting_loader(&loads, 1))), 1);
+        assert_eq!(block_on(cache.get("b", counting_loader(&loads, 2))), 2);
+        // touch "a", so "b" is the least recently used
+        assert_eq!(block_on(cache.get("a", counting_loader(&loads, 0))), 1);
+        assert_eq!(block_on(cache.get("c", counting_loader(&loads, 3))), 3);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(loads.load(Ordering::SeqCst), 3);
+
+        assert_eq!(block_on(cache.get("a", counting_loader(&loads, 0))), 1);
+        assert_eq!(block_on(cache.get("b", counting_loader(&loads, 4))), 4);
+        assert_eq!(loads.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_invalidate() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let cache = CallbackCache::new();
+
+        assert_eq!(block_on(cache.get("a", counting_loader(&loads, 1))), 1);
+        assert_eq!(block_on(cache.get("b", counting_loader(&loads, 2))), 2);
+        cache.invalidate(&"a");
+        assert_eq!(block_on(cache.get("a", counting_loader(&loads, 3))), 3);
+        assert_eq!(block_on(cache.get("b", counting_loader(&loads, 0))), 2);
+        cache.invalidate_all();
+        assert!(cache.is_empty());
+        assert_eq!(block_on(cache.get("b", counting_loader(&loads, 4))), 4);
+        assert_eq!(loads.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_invalidate_in_flight() {
+        let (tx, rx) = mpsc::channel();
+        let loads = Arc::new(AtomicUsize::new(0));
+        let cache = CallbackCache::new();
+        let fu1 = cache.get("a", move |complete| tx.send(complete).unwrap());
+
+        let waiter = thread::spawn(move || block_on(fu1));
+        let complete = rx.recv().unwrap();
+        cache.invalidate(&"a");
+        let fu2 = cache.get("a", counting_loader(&loads, 2));
+        // late completion of invalidated operation doesn't affect the new entry
+        complete(1);
+
+        assert_eq!(waiter.join().unwrap(), 1);
+        assert_eq!(block_on(fu2), 2);
+        assert_eq!(block_on(cache.get("a", counting_loader(&loads, 3))), 2);
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_cache_failures() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let cache = CallbackCache::new();
+
+        assert_eq!(block_on(cache.get("a", counting_loader(&loads, Err(1)))), Err::<(), _>(1));
+        assert_eq!(block_on(cache.get("a", counting_loader(&loads, Ok(())))), Err(1));
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_without_failures() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let cache = CallbackCache::without_failures(CachePolicy::default());
+
+        assert_eq!(block_on(cache.get("a", counting_loader(&loads, Err(1)))), Err(1));
+        assert_eq!(block_on(cache.get("a", counting_loader(&loads, Ok(2)))), Ok(2));
+        assert_eq!(block_on(cache.get("a", counting_loader(&loads, Err(3)))), Ok(2));
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_with_filter() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let cache = CallbackCache::with_filter(CachePolicy::default(), |value: &Option<u32>| value.is_some());
+
+        assert_eq!(block_on(cache.get("a", counting_loader(&loads, None))), None);
+        assert_eq!(block_on(cache.get("a", counting_loader(&loads, Some(2)))), Some(2));
+        assert_eq!(block_on(cache.get("a", counting_loader(&loads, None))), Some(2));
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,284 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::fmt;
+
+use crate::{BoxLoader, CallbackFuture, Completer, Slot};
+
+/// Dispatcher of completions, for callbacks arriving on threads which must not run waker code.
+///
+/// Implemented for `Fn(Completion)` closures and, with the `std` feature, for `mpsc` senders.
+pub trait Executor: Send {
+    /// Schedules the completion to be run
+    ///
+    /// Called on the callback thread; implementations should only enqueue the completion.
+    fn execute(&self, completion: Completion);
+}
+
+impl<F: Fn(Completion) + Send> Executor for F {
+    fn execute(&self, completion: Completion) {
+        self(completion)
+    }
+}
+
+/// Completes the sender's CallbackFuture on the receiving thread.
+///
+/// `send` never allocates for a `sync_channel` and blocks only when the channel is full,
+/// so the bound should cover the number of pending operations. If the receiver is gone,
+/// the completion is run on the callback thread.
+///
+/// `send` is not lock-free: while the receiver is blocked in `recv`, it locks the channel
+/// and unparks the receiving thread. Callbacks on threads where locking is forbidden need
+/// an executor backed by a lock-free queue.
+#[cfg(feature = "std")]
+impl Executor for std::sync::mpsc::SyncSender<Completion> {
+    fn execute(&self, completion: Completion) {
+        if let Err(std::sync::mpsc::SendError(completion)) = self.send(completion) {
+            completion.run();
+        }
+    }
+}
+
+/// Completes the sender's CallbackFuture on the receiving thread.
+///
+/// `send` never blocks, but may allocate. If the receiver is gone, the completion
+/// is run on the callback thread.
+///
+/// Same as for a `SyncSender`, `send` locks the channel and unparks the receiving thread
+/// while the receiver is blocked in `recv`.
+#[cfg(feature = "std")]
+impl Executor for std::sync::mpsc::Sender<Completion> {
+    fn execute(&self, completion: Completion) {
+        if let Err(std::sync::mpsc::SendError(completion)) = self.send(completion) {
+            completion.run();
+        }
+    }
+}
+
+/// Value received by a completion callback, together with its future.
+///
+/// Running it stores the value and wakes the task awaiting the future. Dropping it without
+/// running leaves the future pending forever.
+pub struct Completion(Box<dyn Run>);
+
+impl Completion {
+    /// Stores the value and wakes the task awaiting the future
+    pub fn run(self) {
+        self.0.run()
+    }
+}
+
+impl fmt::Debug for Completion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Completion")
+    }
+}
+
+trait Run: Send {
+    fn run(self: Box<Self>);
+}
+
+/// Allocated upon loader invocation, so that the callback only moves the value in.
+struct Dispatch<T> {
+    slot: Arc<Slot<T>>,
+    value: Option<T>,
+}
+
+impl<T: Send> Run for Dispatch<T> {
+    fn run(self: Box<Self>) {
+        let Dispatch { slot, value } = *self;
+        if let Some(value) = value {
+            // the dispatch is created once per loader invocation and run at most once
+            unsafe { slot.complete(value) };
+        }
+    }
+}
+
+impl<T: Send + 'static> CallbackFuture<T> {
+    /// Creates a new CallbackFuture which is completed by the given executor
+    ///
+    /// The callback hands the value to the executor without allocating; the value is stored
+    /// and the awaiting task is woken when the executor runs the `Completion`. Calling
+    /// the boxed callback frees it on the callback thread, and whether handing the value
+    /// over locks depends on the executor. For callbacks arriving on real-time threads,
+    /// where freeing memory is forbidden as well, use `CallbackFuture::from_fn_with_executor`.
+    ///
+    /// # Examples
+    /// ```
+    /// use callback_future::{CallbackFuture, Completion};
+    /// use futures::executor::block_on;
+    /// use std::sync::mpsc;
+    /// use std::thread;
+    ///
+    /// // completions are run on a worker thread
+    /// let (dispatcher, completions) = mpsc::sync_channel::<Completion>(16);
+    /// thread::spawn(move || completions.into_iter().for_each(Completion::run));
+    ///
+    /// let future = CallbackFuture::with_executor(dispatcher, |complete| {
+    ///     thread::spawn(move || complete("Test"));
+    /// });
+    /// assert_eq!(block_on(future), "Test");
+    /// ```
+    pub fn with_executor(executor: impl Executor + 'static,
+                         loader: impl FnOnce(Box<dyn FnOnce(T) + Send + 'static>) + Send + 'static)
+                         -> CallbackFuture<T> {
+        let slot = Arc::new(Slot::new(None));
+        let dispatch_slot = slot.clone();
+        CallbackFuture {
+            // the completer storing the value directly is replaced by one dispatching it
+            loader: Some(BoxLoader(Box::new(move |_complete| {
+                let mut dispatch = Box::new(Dispatch { slot: dispatch_slot, value: None });
+                loader(Box::new(move |value| {
+                    dispatch.value = Some(value);
+                    executor.execute(Completion(dispatch));
+                }));
+                None
+            }))),
+            cancel: None,
+            slot,
+        }
+    }
+}
+
+impl<T: Send + 'static> CallbackFuture<T> {
+    /// Creates a new CallbackFuture storing the loader without boxing, which is completed
+    /// by the given executor
+    ///
+    /// The loader receives a `DispatchCompleter`, so the callback neither allocates nor frees
+    /// memory: it moves the value into the `Completion` allocated upon loader invocation
+    /// and hands it to the executor. The callback doesn't lock or wake if the executor doesn't.
+    ///
+    /// # Examples
+    /// ```
+    /// use callback_future::{CallbackFuture, Completion};
+    /// use futures::executor::block_on;
+    /// use std::sync::mpsc;
+    /// use std::thread;
+    ///
+    /// // completions are run on a worker thread
+    /// let (dispatcher, completions) = mpsc::sync_channel::<Completion>(16);
+    /// thread::spawn(move || completions.into_iter().for_each(Completion::run));
+    ///
+    /// let future = CallbackFuture::from_fn_with_executor(dispatcher, |completer| {
+    ///     thread::spawn(move || completer.complete("Test"));
+    /// });
+    /// assert_eq!(block_on(future), "Test");
+    /// ```
+    pub fn from_fn_with_executor<X: Executor>(executor: X, loader: impl FnOnce(DispatchCompleter<T, X>))
+                                              -> CallbackFuture<T, impl FnOnce(Completer<T>)> {
+        CallbackFuture::from_fn(move |completer: Completer<T>| {
+            loader(DispatchCompleter {
+                dispatch: Box::new(Dispatch { slot: completer.slot, value: None }),
+                executor,
+            })
+        })
+    }
+}
+
+/// Completer of a CallbackFuture created with `CallbackFuture::from_fn_with_executor`.
+///
+/// Dropping it without completing leaves the future pending forever.
+pub struct DispatchCompleter<T, X> {
+    dispatch: Box<Dispatch<T>>,
+    executor: X,
+}
+
+impl<T: Send + 'static, X: Executor> DispatchCompleter<T, X> {
+    /// Hands the value to the executor, which completes the future
+    ///
+    /// The executor is dropped afterwards, on the calling thread.
+    pub fn complete(self, value: T) {
+        let DispatchCompleter { mut dispatch, executor } = self;
+        dispatch.value = Some(value);
+        executor.execute(Completion(dispatch));
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::Ordering;
+    use std::sync::mpsc;
+    use std::thread;
+
+    use futures::Future;
+    use futures::executor::block_on;
+    use futures::task::{Context, waker};
+
+    use crate::{CallbackFuture, Completion};
+    use crate::test_util::CountingWaker;
+
+    #[test]
+    fn test_complete_on_executor() {
+        let completions = Arc::new(Mutex::new(Vec::new()));
+        let executor_completions = completions.clone();
+        let mut fu = CallbackFuture::with_executor(move |completion| {
+            executor_completions.lock().unwrap().push(completion);
+        }, |complete| complete(42));
+
+        let counter = Arc::new(CountingWaker::default());
+        let waker = waker(counter.clone());
+        assert!(Pin::new(&mut fu).poll(&mut Context::from_waker(&waker)).is_pending());
+
+        // the value is held by the executor until the completion is run
+        assert!(!fu.is_completed());
+        assert_eq!(counter.0.load(Ordering::SeqCst), 0);
+        let completion = completions.lock().unwrap().pop().unwrap();
+        completion.run();
+        assert!(fu.is_completed());
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        assert_eq!(block_on(fu), 42);
+    }
+
+    #[test]
+    fn test_complete_on_worker_thread() {
+        let (dispatcher, completions) = mpsc::sync_channel::<Completion>(4);
+        let worker = thread::spawn(move || {
+            for completion in completions {
+                completion.run();
+            }
+        });
+
+        let fus = (0..8).map(|i| {
+            CallbackFuture::with_executor(dispatcher.clone(), move |complete| {
+                thread::spawn(move || complete(i));
+            })
+        }).collect::<Vec<_>>();
+        drop(dispatcher);
+
+        assert_eq!(block_on(futures::future::join_all(fus)), (0..8).collect::<Vec<_>>());
+        worker.join().unwrap();
+    }
+
+    #[test]
+    fn test_from_fn_on_worker_thread() {
+        let (dispatcher, completions) = mpsc::sync_channel::<Completion>(4);
+        let worker = thread::spawn(move || {
+            for completion in completions {
+                completion.run();
+            }
+        });
+
+        let fus = (0..8).map(|i| {
+            CallbackFuture::from_fn_with_executor(dispatcher.clone(), move |completer| {
+                thread::spawn(move || completer.complete(i));
+            })
+        }).collect::<Vec<_>>();
+        drop(dispatcher);
+
+        assert_eq!(block_on(futures::future::join_all(fus)), (0..8).collect::<Vec<_>>());
+        worker.join().unwrap();
+    }
+
+    #[test]
+    fn test_receiver_dropped() {
+        let (dispatcher, completions) = mpsc::channel::<Completion>();
+        drop(completions);
+        let fu = CallbackFuture::with_executor(dispatcher, |complete| {
+            thread::spawn(move || complete(42));
+        });
+
+        // completed on the callback thread
+        assert_eq!(block_on(fu), 42);
+    }
+}
@@ -0,0 +1,223 @@
+//! Helpers for passing completion callbacks through C APIs.
+//!
+//! C libraries usually accept a callback function pointer together with an opaque
+//! `void *user_data` pointer, which is passed back to the callback. `into_user_data` converts
+//! a Rust callback (e.g. the completer received by `CallbackFuture::new`) into such a pointer,
+//! and the generic `trampoline*` functions are `extern "C"` callbacks which convert it back,
+//! call it and free it.
+//!
+//! Each `user_data` pointer must be consumed exactly once: either by a trampoline called with
+//! the same argument types, or by `drop_user_data` if the C library will never call back.
+//!
+//! # Examples
+//! ```
+//! use callback_future::{ffi, CallbackFuture};
+//! use futures::executor::block_on;
+//! use std::os::raw::{c_int, c_void};
+//!
+//! type Callback = unsafe extern "C" fn(user_data: *mut c_void, status: c_int, result: *const c_int);
+//!
+//! // C library function
+//! extern "C" fn c_compute(cb: Callback, user_data: *mut c_void) -> c_int {
+//!     let result: c_int = 42;
+//!     unsafe { cb(user_data, 0, &result) };
+//!     0
+//! }
+//!
+//! let future = CallbackFuture::new(|complete| {
+//!     let user_data = ffi::into_user_data(move |(status, result): (c_int, *const c_int)| {
+//!         complete(if status == 0 { Ok(unsafe { *result }) } else { Err(status) });
+//!     });
+//!     let trampoline = ffi::trampoline2::<c_int, *const c_int>;
+//!     if c_compute(trampoline, user_data) != 0 {
+//!         // callback will never be called
+//!         unsafe { ffi::drop_user_data::<(c_int, *const c_int)>(user_data) };
+//!     }
+//! });
+//! assert_eq!(block_on(future), Ok(42));
+//! ```
+
+use alloc::boxed::Box;
+use core::ffi::c_void;
+
+type UserData<A> = Box<dyn FnOnce(A) + Send + 'static>;
+
+/// Converts a callback into an opaque `user_data` pointer
+///
+/// The pointer must be passed to a trampoline with matching argument types
+/// or to `drop_user_data` exactly once, otherwise the callback is leaked.
+pub fn into_user_data<A>(callback: impl FnOnce(A) + Send + 'static) -> *mut c_void {
+    // box twice: `UserData` is a fat pointer, and `user_data` has to be a thin one
+    Box::into_raw(Box::new(Box::new(callback) as UserData<A>)) as *mut c_void
+}
+
+/// Calls the callback behind `user_data` and frees it
+///
+/// # Safety
+/// `user_data` must have been returned by `into_user_data` for the callback taking `A`,
+/// and must not be used afterwards.
+pub unsafe fn call_user_data<A>(user_data: *mut c_void, args: A) {
+    let callback = Box::from_raw(user_data as *mut UserData<A>);
+    callback(args)
+}
+
+/// Frees the callback behind `user_data` without calling it
+///
+/// Use it when the C library fails to schedule the callback. For completers of futures
+/// created with `CallbackFuture::try_new` this resolves the future to `Err(Canceled)`.
+///
+/// # Safety
+/// `user_data` must have been returned by `into_user_data` for the callback taking `A`,
+/// and must not be used afterwards.
+pub unsafe fn drop_user_data<A>(user_data: *mut c_void) {
+    drop(Box::from_raw(user_data as *mut UserData<A>))
+}
+
+/// `extern "C"` callback of form `void (*)(void *user_data)`
+///
+/// # Safety
+/// See `call_user_data`, with `A = ()`.
+pub unsafe extern "C" fn trampoline0(user_data: *mut c_void) {
+    call_user_data(user_data, ())
+}
+
+/// `extern "C"` callback of form `void (*)(void *user_data, A a)`
+///
+/// # Safety
+/// See `call_user_data`.
+pub unsafe extern "C" fn trampoline1<A>(user_data: *mut c_void, a: A) {
+    call_user_data(user_data, a)
+}
+
+/// `extern "C"` callback of form `void (*)(void *user_data, A a, B b)`
+///
+/// # Safety
+/// See `call_user_data`, with arguments passed as `(A, B)`.
+pub unsafe extern "C" fn trampoline2<A, B>(user_data: *mut c_void, a: A, b: B) {
+    call_user_data(user_data, (a, b))
+}
+
+/// `extern "C"` callback of form `void (*)(void *user_data, A a, B b, C c)`
+///
+/// # Safety
+/// See `call_user_data`, with arguments passed as `(A, B, C)`.
+pub unsafe extern "C" fn trampoline3<A, B, C>(user_data: *mut c_void, a: A, b: B, c: C) {
+    call_user_data(user_data, (a, b, c))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+    use std::os::raw::{c_char, c_int, c_void};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    use futures::executor::block_on;
+
+    use crate::{Canceled, CallbackFuture, ffi};
+    use crate::test_util::DropCounter;
+
+    // C-style shim: `int shim_fetch(int fail, void (*cb)(void*, int, const char*), void* user_data)`
+    // calls back asynchronously from another thread; returns non-zero without calling back
+    // when asked to fail synchronously
+
+    type ShimCallback = unsafe extern "C" fn(*mut c_void, c_int, *const c_char);
+
+    const SHIM_OK: c_int = 0;
+    const SHIM_ASYNC_ERROR: c_int = 1;
+    const SHIM_SYNC_ERROR: c_int = 2;
+
+    extern "C" fn shim_fetch(fail: c_int, cb: ShimCallback, user_data: *mut c_void) -> c_int {
+        if fail == SHIM_SYNC_ERROR {
+            return -1;
+        }
+        let user_data = user_data as usize;
+        thread::spawn(move || {
+            let result = b"Hello, world!\0";
+            let status = if fail == SHIM_ASYNC_ERROR { -2 } else { 0 };
+            unsafe { cb(user_data as *mut c_void, status, result.as_ptr() as *const c_char) };
+        });
+        0
+    }
+
+    extern "C" fn shim_notify(cb: unsafe extern "C" fn(*mut c_void), user_data: *mut c_void) {
+        unsafe { cb(user_data) };
+    }
+
+    extern "C" fn shim_sum(cb: unsafe extern "C" fn(*mut c_void, c_int, c_int, c_int),
+                           user_data: *mut c_void) {
+        unsafe { cb(user_data, 1, 2, 3) };
+    }
+
+    fn fetch(fail: c_int, drops: Arc<AtomicUsize>) -> CallbackFuture<Result<Result<String, c_int>, Canceled>> {
+        CallbackFuture::try_new(move |complete| {
+            let counter = DropCounter(drops);
+            let user_data = ffi::into_user_data(move |(status, result): (c_int, *const c_char)| {
+                drop(counter);
+                complete(match status {
+                    0 => Ok(unsafe { CStr::from_ptr(result) }.to_string_lossy().into_owned()),
+                    _ => Err(status),
+                });
+            });
+            if shim_fetch(fail, ffi::trampoline2::<c_int, *const c_char>, user_data) != 0 {
+                unsafe { ffi::drop_user_data::<(c_int, *const c_char)>(user_data) };
+            }
+        })
+    }
+
+    #[test]
+    fn test_complete() {
+        let drops = Arc::new(AtomicUsize::new(0));
+
+        assert_eq!(block_on(fetch(SHIM_OK, drops.clone())), Ok(Ok("Hello, world!".to_string())));
+        assert_eq!(drops.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_async_error() {
+        let drops = Arc::new(AtomicUsize::new(0));
+
+        assert_eq!(block_on(fetch(SHIM_ASYNC_ERROR, drops.clone())), Ok(Err(-2)));
+        assert_eq!(drops.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_sync_error() {
+        let drops = Arc::new(AtomicUsize::new(0));
+
+        assert_eq!(block_on(fetch(SHIM_SYNC_ERROR, drops.clone())), Err(Canceled));
+        assert_eq!(drops.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_trampoline0() {
+        let fu = CallbackFuture::<()>::new(|complete| {
+            shim_notify(ffi::trampoline0, ffi::into_user_data(complete));
+        });
+
+        assert_eq!(block_on(fu), ());
+    }
+
+    #[test]
+    fn test_trampoline1() {
+        extern "C" fn shim_value(cb: unsafe extern "C" fn(*mut c_void, f64), user_data: *mut c_void) {
+            unsafe { cb(user_data, 0.5) };
+        }
+
+        let fu = CallbackFuture::<f64>::new(|complete| {
+            shim_value(ffi::trampoline1::<f64>, ffi::into_user_data(complete));
+        });
+
+        assert_eq!(block_on(fu), 0.5);
+    }
+
+    #[test]
+    fn test_trampoline3() {
+        let fu = CallbackFuture::<(c_int, c_int, c_int)>::new(|complete| {
+            shim_sum(ffi::trampoline3::<c_int, c_int, c_int>, ffi::into_user_data(complete));
+        });
+
+        assert_eq!(block_on(fu), (1, 2, 3));
+    }
+}
@@ -1,16 +1,153 @@
-use std::pin::Pin;
-use std::sync::{Arc, Mutex};
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::fmt;
+use core::pin::Pin;
 
 use futures::Future;
 use futures::task::{Context, Poll};
 
+#[cfg(feature = "std")]
+pub use cache::{CachePolicy, CallbackCache};
+#[cfg(feature = "macros")]
+pub use callback_future_macros::asyncify;
+pub use executor::{Completion, DispatchCompleter, Executor};
+pub use local::LocalCallbackFuture;
+#[doc(hidden)]
+pub use macros::__load;
+#[cfg(feature = "std")]
+pub use retry::retry;
+pub use retry::{retry_on, Retry, RetryPolicy};
+#[cfg(feature = "std")]
+pub use shared::SharedCallbackFuture;
+#[cfg(feature = "std")]
+pub use single_flight::SingleFlight;
+use slot::Slot;
+#[cfg(feature = "std")]
+pub use stream::{BufferPolicy, CallbackStream, Emitter};
+#[cfg(feature = "std")]
+pub use timeout::ThreadTimer;
+pub use timeout::{Elapsed, Sleep, Timeout, Timer};
+pub use try_future::{TryCallbackFuture, TryCompleter};
+
+#[cfg(feature = "async-io")]
+pub mod async_io;
+#[cfg(feature = "std")]
+mod cache;
+mod executor;
+pub mod ffi;
+mod local;
+mod macros;
+mod retry;
+#[cfg(feature = "std")]
+mod shared;
+#[cfg(feature = "std")]
+mod single_flight;
+mod slot;
+#[cfg(feature = "std")]
+mod stream;
+#[cfg(test)]
+mod test_util;
+mod timeout;
+#[cfg(feature = "tokio")]
+pub mod tokio;
+mod try_future;
+#[cfg(feature = "std")]
+mod wait;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+type Complete<T> = Box<dyn FnOnce(T) + Send + 'static>;
+type Loader<T> = Box<dyn FnOnce(Complete<T>) -> Option<Box<dyn Cancel>> + Send + 'static>;
+
+/// Cancellation handle of a pending operation, returned by the loader of
+/// `CallbackFuture::with_cancel`.
+///
+/// Implemented for `FnOnce()` closures.
+pub trait Cancel: Send {
+    /// Aborts the pending operation
+    fn cancel(self: Box<Self>);
+}
+
+impl<F: FnOnce() + Send> Cancel for F {
+    fn cancel(self: Box<Self>) {
+        (*self)()
+    }
+}
+
+/// Completion handle passed to the loader of `CallbackFuture::from_fn`.
+pub struct Completer<T> {
+    slot: Arc<Slot<T>>,
+}
+
+impl<T> Completer<T> {
+    /// Completes the future with the given value
+    pub fn complete(self, value: T) {
+        // completer is consumed and the only one for this slot
+        unsafe { self.slot.complete(value) };
+    }
+}
+
+/// Loader of a CallbackFuture, invoked with the completer upon first poll.
+///
+/// Implemented for `FnOnce(Completer<T>)` closures, which are stored without boxing,
+/// and for `BoxLoader`, the loader of futures created with `CallbackFuture::new`.
+pub trait Load<T> {
+    /// Starts the operation, returning its cancellation handle if it has one
+    fn load(self, completer: Completer<T>) -> Option<Box<dyn Cancel>>;
+}
+
+impl<T, F: FnOnce(Completer<T>)> Load<T> for F {
+    fn load(self, completer: Completer<T>) -> Option<Box<dyn Cancel>> {
+        self(completer);
+        None
+    }
+}
+
+/// Type-erased loader of a `CallbackFuture<T>`, created by `CallbackFuture::new`
+/// and `CallbackFuture::with_cancel`.
+pub struct BoxLoader<T>(Loader<T>);
+
+impl<T: Send + 'static> Load<T> for BoxLoader<T> {
+    fn load(self, completer: Completer<T>) -> Option<Box<dyn Cancel>> {
+        (self.0)(Box::new(move |value| completer.complete(value)))
+    }
+}
+
+/// Error returned by a CallbackFuture created with `CallbackFuture::try_new`
+/// when the completion callback is dropped without being called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Canceled;
+
+impl fmt::Display for Canceled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("completion callback was dropped without being called")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Canceled {}
+
 /// An adapter between callbacks and futures.
 ///
 /// Allows wrapping asynchronous API with callbacks into futures.
 /// Calls loader upon first `Future::poll` call; stores result and wakes upon getting callback.
-pub struct CallbackFuture<T> {
-    loader: Option<Box<dyn FnOnce(Box<dyn FnOnce(T) + Send + 'static>) + Send + 'static>>,
-    result: Arc<Mutex<Option<T>>>,
+/// The waker is re-registered on every poll, so the latest task polling the future is woken.
+/// Completion is lock-free, so the callback may be called from an interrupt context.
+///
+/// Only `CallbackFuture::from_fn` allocates once: it stores its loader of type `L` inline
+/// and is completed through a `Completer`, so the shared result slot is its only allocation.
+/// `CallbackFuture<T>` boxes its loader, and its loader receives a boxed completion callback,
+/// so `CallbackFuture::new` and the constructors built on it allocate up to three times:
+/// the loader, unless it captures nothing, the shared result slot, and the completion callback
+/// when the loader is invoked. Use `from_fn` where allocations matter.
+pub struct CallbackFuture<T, L = BoxLoader<T>> {
+    loader: Option<L>,
+    cancel: Option<Box<dyn Cancel>>,
+    slot: Arc<Slot<T>>,
 }
 
 impl<T> CallbackFuture<T> {
@@ -34,8 +171,57 @@ impl<T> CallbackFuture<T> {
     pub fn new(loader: impl FnOnce(Box<dyn FnOnce(T) + Send + 'static>) + Send + 'static)
                -> CallbackFuture<T> {
         CallbackFuture {
-            loader: Some(Box::new(loader)),
-            result: Arc::new(Mutex::new(None)),
+            loader: Some(BoxLoader(Box::new(move |complete| {
+                loader(complete);
+                None
+            }))),
+            cancel: None,
+            slot: Arc::new(Slot::new(None)),
+        }
+    }
+
+    /// Creates a new CallbackFuture which can abort the pending operation
+    ///
+    /// The loader returns a cancellation handle, which is invoked if the future is dropped
+    /// after the loader was called but before the callback fired, e.g. when the future loses
+    /// a `select!` or times out. A callback arriving after cancellation is ignored.
+    ///
+    /// # Examples
+    /// ```
+    /// use callback_future::CallbackFuture;
+    /// use futures::executor::block_on;
+    /// use futures::{future, select, FutureExt};
+    /// use std::sync::Arc;
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    ///
+    /// let canceled = Arc::new(AtomicBool::new(false));
+    /// let request_canceled = canceled.clone();
+    /// let request = CallbackFuture::<&str>::with_cancel(move |complete| {
+    ///     // start request here, keep `complete` until it finishes;
+    ///     // return a handle which aborts the request, e.g.:
+    ///     move || {
+    ///         drop(complete);
+    ///         request_canceled.store(true, Ordering::SeqCst);
+    ///     }
+    /// });
+    /// let result = block_on(async {
+    ///     select! {
+    ///         value = request.fuse() => value,
+    ///         value = future::ready("Fallback") => value,
+    ///     }
+    /// });
+    /// assert_eq!(result, "Fallback");
+    /// assert!(canceled.load(Ordering::SeqCst));
+    /// ```
+    pub fn with_cancel<C: Cancel + 'static>(
+        loader: impl FnOnce(Box<dyn FnOnce(T) + Send + 'static>) -> C + Send + 'static)
+        -> CallbackFuture<T> {
+        CallbackFuture {
+            loader: Some(BoxLoader(Box::new(move |complete| {
+                Some(Box::new(loader(complete)) as Box<dyn Cancel>)
+            }))),
+            cancel: None,
+            slot: Arc::new(Slot::new(None)),
         }
     }
 
@@ -51,34 +237,208 @@ impl<T> CallbackFuture<T> {
     pub fn ready(value: T) -> CallbackFuture<T> {
         CallbackFuture {
             loader: None,
-            result: Arc::new(Mutex::new(Some(value))),
+            cancel: None,
+            slot: Arc::new(Slot::new(Some(value))),
         }
     }
+
+    /// Creates a started CallbackFuture together with the completer of its result
+    ///
+    /// For operations which have to be started by the caller rather than by a loader,
+    /// e.g. methods borrowing `self`. Dropping the completer leaves the future pending forever.
+    ///
+    /// # Examples
+    /// ```
+    /// use callback_future::CallbackFuture;
+    /// use futures::executor::block_on;
+    /// use std::thread;
+    ///
+    /// let (future, completer) = CallbackFuture::channel();
+    /// assert!(future.is_started());
+    /// thread::spawn(move || completer.complete("Test"));
+    /// assert_eq!(block_on(future), "Test");
+    /// ```
+    pub fn channel() -> (CallbackFuture<T>, Completer<T>) {
+        let slot = Arc::new(Slot::new(None));
+        let completer = Completer { slot: slot.clone() };
+        (CallbackFuture { loader: None, cancel: None, slot }, completer)
+    }
 }
 
-impl<T: Send + 'static> Future for CallbackFuture<T> {
+impl<T, L: FnOnce(Completer<T>)> CallbackFuture<T, L> {
+    /// Creates a new CallbackFuture storing the loader without boxing
+    ///
+    /// The loader receives a `Completer` instead of a boxed callback, so creating and
+    /// completing the future allocates only the shared result slot.
+    ///
+    /// # Examples
+    /// ```
+    /// use callback_future::CallbackFuture;
+    /// use futures::executor::block_on;
+    /// use std::thread;
+    ///
+    /// let future = CallbackFuture::from_fn(|completer| {
+    ///     thread::spawn(move || completer.complete("Test"));
+    /// });
+    /// assert_eq!(block_on(future), "Test");
+    /// ```
+    pub fn from_fn(loader: L) -> CallbackFuture<T, L> {
+        CallbackFuture {
+            loader: Some(loader),
+            cancel: None,
+            slot: Arc::new(Slot::new(None)),
+        }
+    }
+}
+
+impl<T, L> CallbackFuture<T, L> {
+    /// Returns `true` if the loader was invoked, or if the future was created ready
+    pub fn is_started(&self) -> bool {
+        self.loader.is_none()
+    }
+
+    /// Returns `true` if the callback fired, or if the future was created ready,
+    /// regardless of whether the result was already taken
+    pub fn is_completed(&self) -> bool {
+        self.slot.is_complete()
+    }
+
+    /// Takes the result if the callback fired, without polling and without invoking the loader
+    ///
+    /// The future must not be polled after the result is taken.
+    ///
+    /// # Examples
+    /// ```
+    /// use callback_future::CallbackFuture;
+    /// use futures::executor::block_on;
+    /// use futures::poll;
+    /// use std::sync::mpsc;
+    ///
+    /// let (tx, rx) = mpsc::channel();
+    /// let mut future = CallbackFuture::new(move |complete| tx.send(complete).unwrap());
+    /// assert!(!future.is_started());
+    ///
+    /// // start the operation
+    /// let _ = block_on(async { poll!(&mut future) });
+    /// assert!(future.is_started());
+    /// assert_eq!(future.try_take(), None);
+    ///
+    /// rx.recv().unwrap()("Test");
+    /// assert!(future.is_completed());
+    /// assert_eq!(future.try_take(), Some("Test"));
+    /// ```
+    pub fn try_take(&mut self) -> Option<T> {
+        self.slot.try_take()
+    }
+}
+
+impl<T: Send + 'static> CallbackFuture<T> {
+    /// Creates a new CallbackFuture and invokes the loader immediately
+    ///
+    /// Unlike `CallbackFuture::new`, the operation starts without waiting for the first poll;
+    /// the result is kept until the future is polled.
+    ///
+    /// # Examples
+    /// ```
+    /// use callback_future::CallbackFuture;
+    /// use futures::executor::block_on;
+    /// use std::sync::mpsc;
+    ///
+    /// let (tx, rx) = mpsc::channel();
+    /// let future = CallbackFuture::start_now(move |complete| {
+    ///     tx.send(()).unwrap();
+    ///     complete("Test");
+    /// });
+    /// // loader was invoked before the future was polled
+    /// assert!(rx.try_recv().is_ok());
+    /// assert_eq!(block_on(future), "Test");
+    /// ```
+    pub fn start_now(loader: impl FnOnce(Box<dyn FnOnce(T) + Send + 'static>) + Send + 'static)
+                     -> CallbackFuture<T> {
+        let mut future = CallbackFuture::new(loader);
+        future.start();
+        future
+    }
+}
+
+impl<T, L: Load<T>> CallbackFuture<T, L> {
+    /// Invokes the loader if it was not yet invoked
+    fn start(&mut self) {
+        if let Some(loader) = self.loader.take() {
+            self.cancel = loader.load(Completer { slot: self.slot.clone() });
+        }
+    }
+}
+
+impl<T: Send + 'static> CallbackFuture<Result<T, Canceled>> {
+    /// Creates a new CallbackFuture which resolves to `Err(Canceled)`
+    /// if the completion callback is dropped without being called
+    ///
+    /// # Examples
+    /// ```
+    /// use callback_future::{Canceled, CallbackFuture};
+    /// use futures::executor::block_on;
+    /// use std::thread;
+    ///
+    /// let future = CallbackFuture::try_new(|complete| {
+    ///     // error path: callback is never called
+    ///     thread::spawn(move || {
+    ///         drop(complete);
+    ///     });
+    /// });
+    /// assert_eq!(block_on(future), Err::<(), _>(Canceled));
+    /// ```
+    pub fn try_new(loader: impl FnOnce(Box<dyn FnOnce(T) + Send + 'static>) + Send + 'static)
+                   -> CallbackFuture<Result<T, Canceled>> {
+        CallbackFuture::new(move |complete| {
+            let guard = CancelGuard(Some(complete));
+            loader(Box::new(move |value| guard.complete(value)));
+        })
+    }
+}
+
+/// Completes with `Err(Canceled)` if dropped before `complete` is called.
+struct CancelGuard<T>(Option<Complete<Result<T, Canceled>>>);
+
+impl<T> CancelGuard<T> {
+    fn complete(mut self, value: T) {
+        if let Some(complete) = self.0.take() {
+            complete(Ok(value));
+        }
+    }
+}
+
+impl<T> Drop for CancelGuard<T> {
+    fn drop(&mut self) {
+        if let Some(complete) = self.0.take() {
+            complete(Err(Canceled));
+        }
+    }
+}
+
+// the loader is never pinned: it is moved out upon first poll
+impl<T, L> Unpin for CallbackFuture<T, L> {}
+
+impl<T, L: Load<T>> Future for CallbackFuture<T, L> {
     type Output = T;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let self_mut = self.get_mut();
-        match self_mut.loader.take() {
-            // in case loader is still present, loader was not yet invoked: invoke it
-            Some(loader) => {
-                let waker = cx.waker().clone();
-                let result = self_mut.result.clone();
-                loader(Box::new(move |value| {
-                    *result.lock().unwrap() = Some(value);
-                    waker.wake();
-                }));
-                Poll::Pending
-            }
-            // in case loader was moved-out: either result is already ready,
-            // or we haven't yet received callback
-            None => {
-                match self_mut.result.lock().unwrap().take() {
-                    Some(value) => Poll::Ready(value),
-                    None => Poll::Pending, // we haven't received callback yet
-                }
+        // in case loader is still present, loader was not yet invoked: invoke it
+        self_mut.start();
+        // either result is already ready, or we haven't yet received callback;
+        // in the latter case waker is registered on every poll: the future may have been
+        // moved to another task since the previous poll, and the callback must wake the latest one
+        self_mut.slot.poll(cx.waker())
+    }
+}
+
+impl<T, L> Drop for CallbackFuture<T, L> {
+    fn drop(&mut self) {
+        if let Some(cancel) = self.cancel.take() {
+            // abort the operation only if callback has not fired yet
+            if !self.slot.is_complete() {
+                cancel.cancel();
             }
         }
     }
@@ -86,12 +446,19 @@ impl<T: Send + 'static> Future for CallbackFuture<T> {
 
 #[cfg(test)]
 mod tests {
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc;
     use std::thread;
     use std::time::Duration;
 
-    use futures::{executor::block_on, join};
+    use futures::{executor::block_on, future, join, poll, select};
+    use futures::{Future, FutureExt};
+    use futures::task::{Context, Poll, waker};
 
-    use crate::CallbackFuture;
+    use crate::{Cancel, Canceled, CallbackFuture};
+    use crate::test_util::CountingWaker;
 
     #[test]
     fn test_complete_async() {
@@ -168,4 +535,319 @@ mod tests {
 
         assert_eq!(block_on(do_async()), "Hello, world!");
     }
+
+    #[test]
+    fn test_wakes_latest_waker() {
+        let (tx, rx) = mpsc::channel();
+        let mut fu = CallbackFuture::new(move |complete| {
+            tx.send(complete).unwrap();
+        });
+
+        let first = Arc::new(CountingWaker::default());
+        let second = Arc::new(CountingWaker::default());
+        let first_waker = waker(first.clone());
+        let second_waker = waker(second.clone());
+
+        assert!(Pin::new(&mut fu).poll(&mut Context::from_waker(&first_waker)).is_pending());
+        assert!(Pin::new(&mut fu).poll(&mut Context::from_waker(&second_waker)).is_pending());
+
+        rx.recv().unwrap()(42);
+
+        assert_eq!(first.0.load(Ordering::SeqCst), 0);
+        assert_eq!(second.0.load(Ordering::SeqCst), 1);
+        assert_eq!(Pin::new(&mut fu).poll(&mut Context::from_waker(&second_waker)), Poll::Ready(42));
+    }
+
+    #[test]
+    fn test_move_between_tasks() {
+        let (tx, rx) = mpsc::channel();
+        let mut fu = CallbackFuture::new(move |complete| {
+            tx.send(complete).unwrap();
+        });
+
+        // poll once from the first task, then abandon it
+        assert!(block_on(async { poll!(&mut fu) }).is_pending());
+
+        let complete = rx.recv().unwrap();
+        let waiter = thread::spawn(move || block_on(fu));
+        thread::sleep(Duration::from_millis(100));
+        complete(42);
+
+        assert_eq!(waiter.join().unwrap(), 42);
+    }
+
+    #[test]
+    fn test_try_complete() {
+        let fu = CallbackFuture::try_new(move |complete| {
+            thread::spawn(move || { complete(42); });
+        });
+
+        assert_eq!(block_on(fu), Ok(42));
+    }
+
+    #[test]
+    fn test_try_dropped_sync() {
+        let fu = CallbackFuture::<Result<i32, _>>::try_new(move |complete| {
+            drop(complete);
+        });
+
+        assert_eq!(block_on(fu), Err(Canceled));
+    }
+
+    #[test]
+    fn test_try_dropped_async() {
+        let fu = CallbackFuture::<Result<i32, _>>::try_new(move |complete| {
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                drop(complete);
+            });
+        });
+
+        assert_eq!(block_on(fu), Err(Canceled));
+    }
+
+    #[test]
+    fn test_cancel_on_drop() {
+        let canceled = Arc::new(AtomicUsize::new(0));
+        let request_canceled = canceled.clone();
+        let mut fu = CallbackFuture::<i32>::with_cancel(move |complete| {
+            move || {
+                drop(complete);
+                request_canceled.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+
+        assert!(block_on(async { poll!(&mut fu) }).is_pending());
+        drop(fu);
+
+        assert_eq!(canceled.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_cancel_select_loser() {
+        let canceled = Arc::new(AtomicUsize::new(0));
+        let request_canceled = canceled.clone();
+        let fu = CallbackFuture::<i32>::with_cancel(move |_complete| {
+            move || { request_canceled.fetch_add(1, Ordering::SeqCst); }
+        });
+
+        let result = block_on(async {
+            select! {
+                value = fu.fuse() => value,
+                value = future::ready(42) => value,
+            }
+        });
+
+        assert_eq!(result, 42);
+        assert_eq!(canceled.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_no_cancel_after_callback() {
+        let canceled = Arc::new(AtomicUsize::new(0));
+        let request_canceled = canceled.clone();
+        let (tx, rx) = mpsc::channel();
+        let mut fu = CallbackFuture::with_cancel(move |complete| {
+            tx.send(complete).unwrap();
+            move || { request_canceled.fetch_add(1, Ordering::SeqCst); }
+        });
+
+        assert!(block_on(async { poll!(&mut fu) }).is_pending());
+        // callback fired, but the result was never polled
+        rx.recv().unwrap()(42);
+        drop(fu);
+
+        assert_eq!(canceled.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_no_cancel_after_completion() {
+        let canceled = Arc::new(AtomicUsize::new(0));
+        let request_canceled = canceled.clone();
+        let fu = CallbackFuture::with_cancel(move |complete| {
+            thread::spawn(move || { complete(42); });
+            move || { request_canceled.fetch_add(1, Ordering::SeqCst); }
+        });
+
+        assert_eq!(block_on(fu), 42);
+        assert_eq!(canceled.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_no_cancel_before_loader() {
+        let canceled = Arc::new(AtomicUsize::new(0));
+        let request_canceled = canceled.clone();
+        let fu = CallbackFuture::<i32>::with_cancel(move |_complete| {
+            move || { request_canceled.fetch_add(1, Ordering::SeqCst); }
+        });
+
+        drop(fu);
+        assert_eq!(canceled.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_cancel_trait_object() {
+        struct Timer(Arc<AtomicUsize>);
+
+        impl Cancel for Timer {
+            fn cancel(self: Box<Self>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let canceled = Arc::new(AtomicUsize::new(0));
+        let timer = Timer(canceled.clone());
+        let mut fu = CallbackFuture::<i32>::with_cancel(move |_complete| timer);
+
+        assert!(block_on(async { poll!(&mut fu) }).is_pending());
+        drop(fu);
+
+        assert_eq!(canceled.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_status() {
+        let (tx, rx) = mpsc::channel();
+        let mut fu = CallbackFuture::new(move |complete| {
+            tx.send(complete).unwrap();
+        });
+        assert!(!fu.is_started());
+        assert!(!fu.is_completed());
+        assert_eq!(fu.try_take(), None);
+
+        assert!(block_on(async { poll!(&mut fu) }).is_pending());
+        assert!(fu.is_started());
+        assert!(!fu.is_completed());
+        assert_eq!(fu.try_take(), None);
+
+        rx.recv().unwrap()(42);
+        assert!(fu.is_completed());
+        assert_eq!(fu.try_take(), Some(42));
+        assert!(fu.is_completed());
+        assert_eq!(fu.try_take(), None);
+    }
+
+    #[test]
+    fn test_status_ready() {
+        let mut fu = CallbackFuture::ready(42);
+
+        assert!(fu.is_started());
+        assert!(fu.is_completed());
+        assert_eq!(fu.try_take(), Some(42));
+    }
+
+    #[test]
+    fn test_try_take_async() {
+        let mut fu = CallbackFuture::new(move |complete| {
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                complete(42);
+            });
+        });
+
+        assert!(block_on(async { poll!(&mut fu) }).is_pending());
+        let value = loop {
+            if let Some(value) = fu.try_take() {
+                break value;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(value, 42);
+    }
+
+    #[test]
+    fn test_start_now_sync() {
+        let started = Arc::new(AtomicUsize::new(0));
+        let loader_started = started.clone();
+        let fu = CallbackFuture::start_now(move |complete| {
+            loader_started.fetch_add(1, Ordering::SeqCst);
+            complete(42);
+        });
+
+        // completion arrived before the first poll
+        assert_eq!(started.load(Ordering::SeqCst), 1);
+        assert!(fu.is_started());
+        assert!(fu.is_completed());
+        assert_eq!(block_on(fu), 42);
+        assert_eq!(started.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_start_now_async() {
+        let (tx, rx) = mpsc::channel();
+        let fu = CallbackFuture::start_now(move |complete| {
+            thread::spawn(move || {
+                complete(42);
+                tx.send(()).unwrap();
+            });
+        });
+
+        // completion arrived before the first poll
+        rx.recv().unwrap();
+        assert!(fu.is_completed());
+        assert_eq!(block_on(fu), 42);
+    }
+
+    #[test]
+    fn test_start_now_pending() {
+        let fu = CallbackFuture::start_now(move |complete| {
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                complete(42);
+            });
+        });
+
+        assert_eq!(block_on(fu), 42);
+    }
+
+    #[test]
+    fn test_from_fn_sync() {
+        let fu = CallbackFuture::from_fn(move |completer| {
+            completer.complete(42);
+        });
+
+        assert_eq!(block_on(fu), 42);
+    }
+
+    #[test]
+    fn test_from_fn_async() {
+        let mut fu = CallbackFuture::from_fn(move |completer| {
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                completer.complete(42);
+            });
+        });
+
+        assert!(!fu.is_started());
+        assert!(block_on(async { poll!(&mut fu) }).is_pending());
+        assert!(fu.is_started());
+        assert_eq!(block_on(fu), 42);
+    }
+
+    #[test]
+    fn test_from_fn_completer_dropped() {
+        let mut fu = CallbackFuture::<i32, _>::from_fn(drop);
+
+        assert!(block_on(async { poll!(&mut fu) }).is_pending());
+        assert!(!fu.is_completed());
+    }
+
+    #[test]
+    fn test_from_fn_local_loader() {
+        // the loader is not required to be `Send`
+        let value = std::rc::Rc::new(42);
+        let fu = CallbackFuture::from_fn(move |completer| completer.complete(*value));
+
+        assert_eq!(block_on(fu), 42);
+    }
+
+    #[test]
+    fn test_channel() {
+        let (mut fu, completer) = CallbackFuture::channel();
+
+        assert!(fu.is_started());
+        assert!(block_on(async { poll!(&mut fu) }).is_pending());
+        thread::spawn(move || completer.complete(42));
+        assert_eq!(block_on(fu), 42);
+    }
 }
@@ -0,0 +1,244 @@
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use core::mem;
+use core::pin::Pin;
+
+use futures::Future;
+use futures::task::{Context, Poll, Waker};
+
+type LocalComplete<T> = Box<dyn FnOnce(T) + 'static>;
+type LocalLoader<T> = Box<dyn FnOnce(LocalComplete<T>) + 'static>;
+
+enum LocalState<T> {
+    Pending(Option<Waker>),
+    Complete(T),
+    Taken,
+}
+
+/// A single-threaded adapter between callbacks and futures.
+///
+/// Same as `CallbackFuture`, but neither the loader, the completion callback nor the result
+/// have to be `Send`, e.g. for `Rc`-based GUI toolkits. The future itself is not `Send`, so it is
+/// meant for single-threaded executors such as `LocalPool`, and the callback must be called
+/// on the same thread.
+pub struct LocalCallbackFuture<T> {
+    loader: Option<LocalLoader<T>>,
+    state: Rc<RefCell<LocalState<T>>>,
+}
+
+impl<T: 'static> LocalCallbackFuture<T> {
+    /// Creates a new LocalCallbackFuture
+    ///
+    /// # Examples
+    /// ```
+    /// use callback_future::LocalCallbackFuture;
+    /// use futures::executor::LocalPool;
+    /// use futures::task::LocalSpawnExt;
+    /// use std::cell::RefCell;
+    /// use std::rc::Rc;
+    ///
+    /// // callbacks queued by a single-threaded event loop
+    /// let event_loop: Rc<RefCell<Vec<Box<dyn FnOnce()>>>> = Rc::default();
+    ///
+    /// let mut pool = LocalPool::new();
+    /// let loop_handle = event_loop.clone();
+    /// let result = Rc::new(RefCell::new(None));
+    /// let task_result = result.clone();
+    /// pool.spawner().spawn_local(async move {
+    ///     let label = LocalCallbackFuture::new(move |complete| {
+    ///         loop_handle.borrow_mut().push(Box::new(move || complete(Rc::new("Test"))));
+    ///     }).await;
+    ///     *task_result.borrow_mut() = Some(*label);
+    /// }).unwrap();
+    ///
+    /// pool.run_until_stalled();
+    /// for callback in event_loop.take() {
+    ///     callback();
+    /// }
+    /// pool.run_until_stalled();
+    /// assert_eq!(*result.borrow(), Some("Test"));
+    /// ```
+    pub fn new(loader: impl FnOnce(Box<dyn FnOnce(T) + 'static>) + 'static) -> LocalCallbackFuture<T> {
+        LocalCallbackFuture {
+            loader: Some(Box::new(loader)),
+            state: Rc::new(RefCell::new(LocalState::Pending(None))),
+        }
+    }
+}
+
+impl<T> LocalCallbackFuture<T> {
+    /// Creates a ready LocalCallbackFuture
+    pub fn ready(value: T) -> LocalCallbackFuture<T> {
+        LocalCallbackFuture {
+            loader: None,
+            state: Rc::new(RefCell::new(LocalState::Complete(value))),
+        }
+    }
+
+    /// Returns `true` if the loader was invoked, or if the future was created ready
+    pub fn is_started(&self) -> bool {
+        self.loader.is_none()
+    }
+
+    /// Returns `true` if the callback fired, or if the future was created ready
+    pub fn is_completed(&self) -> bool {
+        !matches!(*self.state.borrow(), LocalState::Pending(_))
+    }
+}
+
+impl<T: 'static> Future for LocalCallbackFuture<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let self_mut = self.get_mut();
+        // the state is not borrowed while the loader runs, as the callback may be called synchronously
+        if let Some(loader) = self_mut.loader.take() {
+            let state = self_mut.state.clone();
+            loader(Box::new(move |value| {
+                let previous = mem::replace(&mut *state.borrow_mut(), LocalState::Complete(value));
+                // wake after releasing the state
+                if let LocalState::Pending(Some(waker)) = previous {
+                    waker.wake();
+                }
+            }));
+        }
+        let mut state = self_mut.state.borrow_mut();
+        match &mut *state {
+            LocalState::Pending(Some(waker)) if waker.will_wake(cx.waker()) => Poll::Pending,
+            LocalState::Pending(waker) => {
+                *waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            LocalState::Complete(_) => match mem::replace(&mut *state, LocalState::Taken) {
+                LocalState::Complete(value) => Poll::Ready(value),
+                _ => unreachable!(),
+            },
+            LocalState::Taken => panic!("LocalCallbackFuture polled after completion"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    use futures::executor::{block_on, LocalPool};
+    use futures::poll;
+    use futures::task::LocalSpawnExt;
+
+    use crate::LocalCallbackFuture;
+
+    /// Callbacks of a simulated single-threaded event loop
+    type EventLoop = Rc<RefCell<Vec<Box<dyn FnOnce()>>>>;
+
+    fn run_callbacks(event_loop: &EventLoop) {
+        for callback in event_loop.take() {
+            callback();
+        }
+    }
+
+    #[test]
+    fn test_complete_sync() {
+        let fu = LocalCallbackFuture::new(move |complete| {
+            complete(Rc::new(42));
+        });
+
+        assert_eq!(*block_on(fu), 42);
+    }
+
+    #[test]
+    fn test_complete_later() {
+        let event_loop = EventLoop::default();
+        let loop_handle = event_loop.clone();
+        let mut fu = LocalCallbackFuture::new(move |complete| {
+            loop_handle.borrow_mut().push(Box::new(move || complete(Rc::new(42))));
+        });
+
+        assert!(!fu.is_started());
+        assert!(block_on(async { poll!(&mut fu) }).is_pending());
+        assert!(fu.is_started());
+        assert!(!fu.is_completed());
+
+        run_callbacks(&event_loop);
+        assert!(fu.is_completed());
+        assert_eq!(*block_on(fu), 42);
+    }
+
+    #[test]
+    fn test_local_pool() {
+        let event_loop = EventLoop::default();
+        let results = Rc::new(RefCell::new(Vec::new()));
+        let mut pool = LocalPool::new();
+        for i in 0..4 {
+            let loop_handle = event_loop.clone();
+            let results = results.clone();
+            pool.spawner().spawn_local(async move {
+                let value = LocalCallbackFuture::new(move |complete| {
+                    loop_handle.borrow_mut().push(Box::new(move || complete(Rc::new(i))));
+                }).await;
+                results.borrow_mut().push(*value);
+            }).unwrap();
+        }
+
+        pool.run_until_stalled();
+        assert!(results.borrow().is_empty());
+        run_callbacks(&event_loop);
+        pool.run_until_stalled();
+        assert_eq!(*results.borrow(), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_wakes_latest_waker() {
+        let event_loop = EventLoop::default();
+        let loop_handle = event_loop.clone();
+        let fu = LocalCallbackFuture::new(move |complete| {
+            loop_handle.borrow_mut().push(Box::new(move || complete(42)));
+        });
+        let fu = Rc::new(RefCell::new(Some(fu)));
+        let done = Rc::new(Cell::new(false));
+
+        let mut pool = LocalPool::new();
+        // first task polls the future once, then hands it over to the second one
+        let first = fu.clone();
+        pool.spawner().spawn_local(async move {
+            let mut fu = first.borrow_mut().take().unwrap();
+            assert!(poll!(&mut fu).is_pending());
+            *first.borrow_mut() = Some(fu);
+        }).unwrap();
+        pool.run_until_stalled();
+
+        let second_done = done.clone();
+        pool.spawner().spawn_local(async move {
+            let fu = fu.borrow_mut().take().unwrap();
+            assert_eq!(fu.await, 42);
+            second_done.set(true);
+        }).unwrap();
+        pool.run_until_stalled();
+
+        run_callbacks(&event_loop);
+        pool.run_until_stalled();
+        assert!(done.get());
+    }
+
+    #[test]
+    fn test_ready() {
+        let fu = LocalCallbackFuture::ready(Rc::new(42));
+
+        assert!(fu.is_started());
+        assert!(fu.is_completed());
+        assert_eq!(*block_on(fu), 42);
+    }
+
+    #[test]
+    #[should_panic(expected = "LocalCallbackFuture polled after completion")]
+    fn test_poll_after_completion() {
+        let mut fu = LocalCallbackFuture::ready(42);
+
+        block_on(async {
+            assert_eq!(poll!(&mut fu), std::task::Poll::Ready(42));
+            let _ = poll!(&mut fu);
+        });
+    }
+}
@@ -0,0 +1,173 @@
+/// Creates a `CallbackFuture` from a loader receiving a multi-argument completion callback.
+///
+/// The first part is the signature of the callback: `|a: A, b: B, ...|`. Without a mapping step
+/// the future resolves to the tuple of arguments `(A, B, ...)`, to the argument itself for
+/// a callback with a single one, and to `()` for a callback without arguments. With a mapping
+/// step, `|a: A, b: &B| -> T { ... }`, the arguments may be borrowed or not `Send`, and
+/// the future resolves to the result of the block, which is evaluated in the callback.
+///
+/// The loader is a closure receiving the callback, which is invoked upon first poll,
+/// same as with `CallbackFuture::new`.
+///
+/// # Examples
+/// ```
+/// use callback_future::callback_future;
+/// use futures::executor::block_on;
+/// use std::ffi::c_void;
+/// use std::thread;
+///
+/// // callback arguments borrow data owned by the API
+/// fn read(callback: impl FnOnce(i32, &[u8], *mut c_void) + Send + 'static) {
+///     thread::spawn(move || callback(0, b"data", std::ptr::null_mut()));
+/// }
+///
+/// let future = callback_future!(|status: i32, bytes: &[u8], _user: *mut c_void| -> (i32, Vec<u8>) {
+///     (status, bytes.to_vec())
+/// }, |complete| read(complete));
+/// assert_eq!(block_on(future), (0, b"data".to_vec()));
+///
+/// let future = callback_future!(|status: i32, message: String|, |complete| {
+///     thread::spawn(move || complete(0, "Test".to_string()));
+/// });
+/// assert_eq!(block_on(future), (0, "Test".to_string()));
+/// ```
+#[macro_export]
+macro_rules! callback_future {
+    (|| -> $out:ty $map:block, $loader:expr $(,)?) => {
+        $crate::CallbackFuture::<$out>::new(move |complete| {
+            let completer = move || complete($map);
+            $crate::__load($loader, completer);
+        })
+    };
+    (||, $loader:expr $(,)?) => {
+        $crate::callback_future!(|| -> () {}, $loader)
+    };
+    (|$($arg:ident: $ty:ty),+ $(,)?| -> $out:ty $map:block, $loader:expr $(,)?) => {
+        $crate::CallbackFuture::<$out>::new(move |complete| {
+            let completer = move |$($arg: $ty),+| complete($map);
+            $crate::__load($loader, completer);
+        })
+    };
+    (|$arg:ident: $ty:ty $(,)?|, $loader:expr $(,)?) => {
+        $crate::callback_future!(|$arg: $ty| -> $ty { $arg }, $loader)
+    };
+    (|$($arg:ident: $ty:ty),+ $(,)?|, $loader:expr $(,)?) => {
+        $crate::callback_future!(|$($arg: $ty),+| -> ($($ty,)+) { ($($arg,)+) }, $loader)
+    };
+}
+
+/// Invokes the loader of `callback_future!` with the completer, which gives the loader closure
+/// the type of its parameter
+#[doc(hidden)]
+pub fn __load<C, L: FnOnce(C)>(loader: L, completer: C) {
+    loader(completer)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::c_void;
+    use std::sync::mpsc;
+    use std::thread;
+
+    use futures::executor::block_on;
+
+    use crate::CallbackFuture;
+
+    #[test]
+    fn test_no_arguments() {
+        let fu: CallbackFuture<()> = callback_future!(||, |complete| {
+            thread::spawn(complete);
+        });
+
+        block_on(fu);
+    }
+
+    #[test]
+    fn test_no_arguments_mapped() {
+        let fu = callback_future!(|| -> i32 { 42 }, |complete| complete());
+
+        assert_eq!(block_on(fu), 42);
+    }
+
+    #[test]
+    fn test_one_argument() {
+        let fu = callback_future!(|value: i32|, |complete| complete(42));
+
+        assert_eq!(block_on(fu), 42);
+    }
+
+    #[test]
+    fn test_two_arguments_async() {
+        let fu = callback_future!(|status: i32, message: String|, move |complete| {
+            thread::spawn(move || complete(404, "Not Found".to_string()));
+        });
+
+        assert_eq!(block_on(fu), (404, "Not Found".to_string()));
+    }
+
+    #[test]
+    fn test_eight_arguments() {
+        let fu = callback_future!(
+            |a: u8, b: u16, c: u32, d: u64, e: i8, f: i16, g: i32, h: i64|,
+            |complete| complete(1, 2, 3, 4, 5, 6, 7, 8),
+        );
+
+        assert_eq!(block_on(fu), (1, 2, 3, 4, 5, 6, 7, 8));
+    }
+
+    #[test]
+    fn test_borrowed_arguments() {
+        fn read(callback: impl FnOnce(i32, &[u8], &str) + Send + 'static) {
+            thread::spawn(move || {
+                let buffer = vec![1, 2, 3];
+                callback(0, &buffer, "ok");
+            });
+        }
+
+        let fu = callback_future!(|status: i32, bytes: &[u8], message: &str| -> Result<Vec<u8>, String> {
+            match status {
+                0 => Ok(bytes.to_vec()),
+                _ => Err(message.to_string()),
+            }
+        }, |complete| read(complete));
+
+        assert_eq!(block_on(fu), Ok(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn test_pointer_argument() {
+        // the pointer is not `Send`, so it must be mapped out
+        let (tx, rx) = mpsc::channel();
+        let fu = callback_future!(|status: i32, _user: *mut c_void| -> i32 { status }, move |complete| {
+            tx.send(complete).unwrap();
+        });
+
+        let waiter = thread::spawn(move || block_on(fu));
+        let complete = rx.recv().unwrap();
+        let mut user = 0;
+        complete(42, &mut user as *mut i32 as *mut c_void);
+        assert_eq!(waiter.join().unwrap(), 42);
+    }
+
+    #[test]
+    fn test_caller_load_function() {
+        // not shadowed by the expansion
+        fn load(value: i32, complete: impl FnOnce(i32)) {
+            complete(value);
+        }
+
+        let fu = callback_future!(|value: i32|, |complete| load(42, complete));
+
+        assert_eq!(block_on(fu), 42);
+    }
+
+    #[test]
+    fn test_captured_loader_state() {
+        let prefix = "Hello, ".to_string();
+        let fu = callback_future!(|name: &str| -> String { format!("{}{}", prefix, name) }, |complete| {
+            complete("world");
+        });
+
+        assert_eq!(block_on(fu), "Hello, world");
+    }
+}
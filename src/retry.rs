@@ -0,0 +1,435 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+
+use futures::Future;
+use futures::task::{Context, Poll};
+
+#[cfg(feature = "std")]
+use crate::ThreadTimer;
+use crate::{Sleep, Timer, TryCallbackFuture, TryCompleter};
+
+type Factory<T, E> = Arc<dyn Fn(TryCompleter<T, E>) + Send + Sync + 'static>;
+
+/// Delay between attempts of a `Retry`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backoff {
+    Fixed(Duration),
+    Exponential { initial: Duration, max: Duration },
+}
+
+/// Policy deciding whether and when a failed attempt of a `Retry` is repeated.
+///
+/// Attempts are not limited and every error is retried unless configured otherwise.
+pub struct RetryPolicy<E> {
+    backoff: Backoff,
+    jitter: bool,
+    seed: Option<u64>,
+    max_attempts: Option<u32>,
+    predicate: Box<dyn Fn(&E) -> bool + Send + Sync + 'static>,
+}
+
+impl<E> RetryPolicy<E> {
+    /// Creates a policy waiting the same delay before every retry
+    pub fn fixed(delay: Duration) -> RetryPolicy<E> {
+        RetryPolicy::with_backoff(Backoff::Fixed(delay))
+    }
+
+    /// Creates a policy doubling the delay before every retry, starting with `initial`
+    /// and never exceeding `max`
+    pub fn exponential(initial: Duration, max: Duration) -> RetryPolicy<E> {
+        RetryPolicy::with_backoff(Backoff::Exponential { initial, max })
+    }
+
+    fn with_backoff(backoff: Backoff) -> RetryPolicy<E> {
+        RetryPolicy {
+            backoff,
+            jitter: false,
+            seed: None,
+            max_attempts: None,
+            predicate: Box::new(|_| true),
+        }
+    }
+
+    /// Limits the number of attempts, including the first one
+    ///
+    /// # Panics
+    /// Panics if `max_attempts` is zero.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> RetryPolicy<E> {
+        assert!(max_attempts > 0, "max_attempts must be greater than zero");
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    /// Randomizes every delay between half of it and the full delay, so that clients
+    /// failing together don't retry together
+    ///
+    /// With the `std` feature the random sequence is seeded per process. Without it there is
+    /// no source of entropy, so every process produces the same sequence unless seeded
+    /// with `with_jitter_seed`.
+    pub fn with_jitter(mut self) -> RetryPolicy<E> {
+        self.jitter = true;
+        self
+    }
+
+    /// Same as `with_jitter`, with the random sequence seeded from the given value,
+    /// e.g. a device identifier or the output of a hardware random number generator
+    ///
+    /// The sequence depends only on the seed, so retries created with the same seed
+    /// wait the same delays.
+    pub fn with_jitter_seed(mut self, seed: u64) -> RetryPolicy<E> {
+        self.jitter = true;
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Retries only the errors for which the predicate returns `true`; other errors are returned
+    pub fn with_predicate(mut self, predicate: impl Fn(&E) -> bool + Send + Sync + 'static) -> RetryPolicy<E> {
+        self.predicate = Box::new(predicate);
+        self
+    }
+
+    /// Returns the delay before the attempt following the given number of failed attempts
+    fn delay(&self, failures: u32, rng: &mut Rng) -> Duration {
+        let delay = match self.backoff {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { initial, max } => {
+                1u32.checked_shl(failures - 1)
+                    .and_then(|factor| initial.checked_mul(factor))
+                    .map_or(max, |delay| delay.min(max))
+            }
+        };
+        match self.jitter {
+            true => {
+                let nanos = delay.as_nanos().min(u64::MAX as u128) as u64;
+                Duration::from_nanos(nanos - rng.next() % (nanos / 2 + 1))
+            }
+            false => delay,
+        }
+    }
+}
+
+/// xorshift64* generator for jitter; not suitable for anything else.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: Option<u64>) -> Rng {
+        // distinct sequences for retries created together without a seed
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let seed = seed.unwrap_or_else(|| entropy() ^ COUNTER.fetch_add(1, Ordering::Relaxed) as u64);
+        let mut seed = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        seed = (seed ^ (seed >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        seed = (seed ^ (seed >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        Rng((seed ^ (seed >> 31)) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+/// Returns a value random per process, from the randomly keyed hasher of `HashMap`
+#[cfg(feature = "std")]
+fn entropy() -> u64 {
+    use std::hash::{BuildHasher, Hasher};
+
+    std::collections::hash_map::RandomState::new().build_hasher().finish()
+}
+
+#[cfg(not(feature = "std"))]
+fn entropy() -> u64 {
+    0
+}
+
+enum RetryState<T, E> {
+    Attempt(TryCallbackFuture<T, E>),
+    Sleep(Sleep),
+    Done,
+}
+
+/// Future returned by `retry` and `retry_on`.
+///
+/// Invokes the loader again after each failure allowed by its `RetryPolicy`,
+/// and resolves to the first success or the last error.
+/// Dropping it drops the pending attempt or delay.
+pub struct Retry<T, E> {
+    factory: Factory<T, E>,
+    policy: RetryPolicy<E>,
+    timer: Box<dyn Timer + Send + 'static>,
+    rng: Rng,
+    attempts: u32,
+    state: RetryState<T, E>,
+}
+
+/// Creates a future invoking the loader again on failure, according to the policy,
+/// using the built-in `ThreadTimer` for delays
+///
+/// # Examples
+/// ```
+/// use callback_future::{retry, RetryPolicy};
+/// use futures::executor::block_on;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::thread;
+/// use std::time::Duration;
+///
+/// let attempts = AtomicUsize::new(0);
+/// let future = retry(move |completer| {
+///     let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+///     thread::spawn(move || match attempt {
+///         0 | 1 => completer.complete_err("unavailable"),
+///         _ => completer.complete_ok(attempt),
+///     });
+/// }, RetryPolicy::fixed(Duration::from_millis(10)).with_max_attempts(5));
+/// assert_eq!(block_on(future), Ok(2));
+/// ```
+#[cfg(feature = "std")]
+pub fn retry<T: 'static, E: 'static>(factory: impl Fn(TryCompleter<T, E>) + Send + Sync + 'static,
+                                     policy: RetryPolicy<E>) -> Retry<T, E> {
+    retry_on(ThreadTimer, factory, policy)
+}
+
+/// Creates a future invoking the loader again on failure, according to the policy,
+/// using the given timer for delays
+pub fn retry_on<T: 'static, E: 'static>(timer: impl Timer + Send + 'static,
+                                        factory: impl Fn(TryCompleter<T, E>) + Send + Sync + 'static,
+                                        policy: RetryPolicy<E>) -> Retry<T, E> {
+    let factory: Factory<T, E> = Arc::new(factory);
+    Retry {
+        state: RetryState::Attempt(attempt(&factory)),
+        factory,
+        rng: Rng::new(policy.seed),
+        policy,
+        timer: Box::new(timer),
+        attempts: 1,
+    }
+}
+
+fn attempt<T: 'static, E: 'static>(factory: &Factory<T, E>) -> TryCallbackFuture<T, E> {
+    let factory = factory.clone();
+    TryCallbackFuture::new(move |completer| factory(completer))
+}
+
+impl<T: Send + 'static, E: Send + 'static> Future for Retry<T, E> {
+    type Output = Result<T, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let self_mut = self.get_mut();
+        loop {
+            match &mut self_mut.state {
+                RetryState::Attempt(future) => {
+                    let error = match Pin::new(future).poll(cx) {
+                        Poll::Ready(Ok(value)) => {
+                            self_mut.state = RetryState::Done;
+                            return Poll::Ready(Ok(value));
+                        }
+                        Poll::Ready(Err(error)) => error,
+                        Poll::Pending => return Poll::Pending,
+                    };
+                    let policy = &self_mut.policy;
+                    if policy.max_attempts.is_some_and(|max| self_mut.attempts >= max) || !(policy.predicate)(&error) {
+                        self_mut.state = RetryState::Done;
+                        return Poll::Ready(Err(error));
+                    }
+                    let delay = policy.delay(self_mut.attempts, &mut self_mut.rng);
+                    self_mut.state = RetryState::Sleep(self_mut.timer.sleep(delay));
+                }
+                RetryState::Sleep(sleep) => {
+                    if sleep.as_mut().poll(cx).is_pending() {
+                        return Poll::Pending;
+                    }
+                    self_mut.attempts += 1;
+                    self_mut.state = RetryState::Attempt(attempt(&self_mut.factory));
+                }
+                RetryState::Done => panic!("Retry polled after completion"),
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    use futures::executor::block_on;
+    use futures::future;
+
+    use crate::{retry, retry_on, RetryPolicy, Sleep, Timer, TryCompleter};
+
+    /// Completes every sleep immediately, recording the requested delays.
+    #[derive(Clone, Default)]
+    struct RecordingTimer(Arc<Mutex<Vec<Duration>>>);
+
+    impl RecordingTimer {
+        fn delays(&self) -> Vec<Duration> {
+            self.0.lock().unwrap().clone()
+        }
+    }
+
+    impl Timer for RecordingTimer {
+        fn sleep(&self, duration: Duration) -> Sleep {
+            self.0.lock().unwrap().push(duration);
+            Box::pin(future::ready(()))
+        }
+    }
+
+    fn millis(millis: &[u64]) -> Vec<Duration> {
+        millis.iter().copied().map(Duration::from_millis).collect()
+    }
+
+    #[test]
+    fn test_success_after_failures() {
+        let timer = RecordingTimer::default();
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let loader_attempts = attempts.clone();
+        let fu = retry_on(timer.clone(), move |completer| {
+            match loader_attempts.fetch_add(1, Ordering::SeqCst) {
+                0 | 1 => completer.complete_err("error"),
+                attempt => completer.complete_ok(attempt),
+            }
+        }, RetryPolicy::fixed(Duration::from_millis(10)));
+
+        assert_eq!(block_on(fu), Ok(2));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        assert_eq!(timer.delays(), millis(&[10, 10]));
+    }
+
+    #[test]
+    fn test_first_attempt_succeeds() {
+        let timer = RecordingTimer::default();
+        let fu = retry_on(timer.clone(), move |completer: TryCompleter<_, ()>| {
+            completer.complete_ok(42);
+        }, RetryPolicy::fixed(Duration::from_millis(10)));
+
+        assert_eq!(block_on(fu), Ok(42));
+        assert!(timer.delays().is_empty());
+    }
+
+    #[test]
+    fn test_max_attempts() {
+        let timer = RecordingTimer::default();
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let loader_attempts = attempts.clone();
+        let fu = retry_on(timer.clone(), move |completer: TryCompleter<(), _>| {
+            completer.complete_err(loader_attempts.fetch_add(1, Ordering::SeqCst));
+        }, RetryPolicy::fixed(Duration::from_millis(10)).with_max_attempts(3));
+
+        // the last error is returned
+        assert_eq!(block_on(fu), Err(2));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        assert_eq!(timer.delays().len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "max_attempts must be greater than zero")]
+    fn test_zero_max_attempts() {
+        RetryPolicy::<()>::fixed(Duration::from_millis(10)).with_max_attempts(0);
+    }
+
+    #[test]
+    fn test_predicate() {
+        let timer = RecordingTimer::default();
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let loader_attempts = attempts.clone();
+        let fu = retry_on(timer.clone(), move |completer: TryCompleter<(), _>| {
+            match loader_attempts.fetch_add(1, Ordering::SeqCst) {
+                0 => completer.complete_err("timeout"),
+                _ => completer.complete_err("not found"),
+            }
+        }, RetryPolicy::fixed(Duration::from_millis(10)).with_predicate(|error| *error == "timeout"));
+
+        assert_eq!(block_on(fu), Err("not found"));
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_exponential_backoff() {
+        let timer = RecordingTimer::default();
+        let fu = retry_on(timer.clone(), move |completer: TryCompleter<(), _>| {
+            completer.complete_err(());
+        }, RetryPolicy::exponential(Duration::from_millis(10), Duration::from_millis(100))
+            .with_max_attempts(7));
+
+        assert_eq!(block_on(fu), Err(()));
+        assert_eq!(timer.delays(), millis(&[10, 20, 40, 80, 100, 100]));
+    }
+
+    #[test]
+    fn test_exponential_backoff_overflow() {
+        let timer = RecordingTimer::default();
+        let fu = retry_on(timer.clone(), move |completer: TryCompleter<(), _>| {
+            completer.complete_err(());
+        }, RetryPolicy::exponential(Duration::from_secs(1), Duration::from_secs(60))
+            .with_max_attempts(40));
+
+        assert_eq!(block_on(fu), Err(()));
+        assert_eq!(timer.delays().last(), Some(&Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_jitter() {
+        let timer = RecordingTimer::default();
+        let fu = retry_on(timer.clone(), move |completer: TryCompleter<(), _>| {
+            completer.complete_err(());
+        }, RetryPolicy::exponential(Duration::from_millis(100), Duration::from_secs(1))
+            .with_jitter()
+            .with_max_attempts(50));
+
+        assert_eq!(block_on(fu), Err(()));
+        let delays = timer.delays();
+        for (failures, delay) in delays.iter().enumerate() {
+            let backoff = Duration::from_millis(100 << failures.min(4)).min(Duration::from_secs(1));
+            assert!(*delay <= backoff && *delay >= backoff / 2, "{:?} for {:?}", delay, backoff);
+        }
+        // delays are randomized
+        assert!(delays.iter().skip(4).any(|delay| *delay != Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_jitter_seeded() {
+        let run = || {
+            let timer = RecordingTimer::default();
+            let fu = retry_on(timer.clone(), move |completer: TryCompleter<(), _>| {
+                completer.complete_err(());
+            }, RetryPolicy::fixed(Duration::from_secs(1))
+                .with_jitter_seed(42)
+                .with_max_attempts(10));
+            assert_eq!(block_on(fu), Err(()));
+            timer.delays()
+        };
+
+        let delays = run();
+        assert!(delays.iter().all(|delay| *delay <= Duration::from_secs(1) && *delay >= Duration::from_millis(500)));
+        // the sequence is reproducible
+        assert_eq!(run(), delays);
+    }
+
+    #[test]
+    fn test_entropy() {
+        assert_ne!(super::entropy(), super::entropy());
+    }
+
+    #[test]
+    fn test_thread_timer_async_completion() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let loader_attempts = attempts.clone();
+        let fu = retry(move |completer| {
+            let attempt = loader_attempts.fetch_add(1, Ordering::SeqCst);
+            thread::spawn(move || match attempt {
+                0 => completer.complete_err("error"),
+                _ => completer.complete_ok(attempt),
+            });
+        }, RetryPolicy::fixed(Duration::from_millis(10)));
+
+        // the future can be moved to another thread
+        assert_eq!(thread::spawn(move || block_on(fu)).join().unwrap(), Ok(1));
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use futures::Future;
+use futures::task::{Context, Poll, Waker};
+
+use crate::Complete;
+
+type SharedLoader<T> = Box<dyn FnOnce(Complete<T>) + Send + 'static>;
+
+/// State shared between all clones of a SharedCallbackFuture and the completion callback.
+struct SharedState<T> {
+    loader: Option<SharedLoader<T>>,
+    value: Option<T>,
+    wakers: HashMap<usize, Waker>,
+    next_key: usize,
+}
+
+impl<T> SharedState<T> {
+    fn next_key(&mut self) -> usize {
+        let key = self.next_key;
+        self.next_key += 1;
+        key
+    }
+}
+
+/// A cloneable adapter between callbacks and futures, for multiple awaiters of one result.
+///
+/// Calls loader upon first `Future::poll` call of any clone; upon getting callback stores
+/// the result and wakes every clone waiting for it. Each clone resolves to a clone of the result.
+pub struct SharedCallbackFuture<T> {
+    state: Arc<Mutex<SharedState<T>>>,
+    key: usize,
+}
+
+impl<T> SharedCallbackFuture<T> {
+    /// Creates a new SharedCallbackFuture
+    ///
+    /// # Examples
+    /// ```
+    /// use callback_future::SharedCallbackFuture;
+    /// use futures::executor::block_on;
+    /// use futures::join;
+    /// use std::thread;
+    ///
+    /// let token = SharedCallbackFuture::new(|complete| {
+    ///     // the request is made once for all awaiters
+    ///     thread::spawn(move || complete("Token".to_string()));
+    /// });
+    /// let (t1, t2) = block_on(async { join!(token.clone(), token) });
+    /// assert_eq!((t1.as_str(), t2.as_str()), ("Token", "Token"));
+    /// ```
+    pub fn new(loader: impl FnOnce(Box<dyn FnOnce(T) + Send + 'static>) + Send + 'static)
+               -> SharedCallbackFuture<T> {
+        SharedCallbackFuture::with_state(Some(Box::new(loader)), None)
+    }
+
+    /// Creates a ready SharedCallbackFuture
+    pub fn ready(value: T) -> SharedCallbackFuture<T> {
+        SharedCallbackFuture::with_state(None, Some(value))
+    }
+
+    fn with_state(loader: Option<SharedLoader<T>>, value: Option<T>) -> SharedCallbackFuture<T> {
+        SharedCallbackFuture {
+            state: Arc::new(Mutex::new(SharedState {
+                loader,
+                value,
+                wakers: HashMap::new(),
+                next_key: 1,
+            })),
+            key: 0,
+        }
+    }
+
+    /// Returns `true` if the callback fired, or if the future was created ready
+    pub fn is_completed(&self) -> bool {
+        self.state.lock().unwrap().value.is_some()
+    }
+}
+
+impl<T: Clone> SharedCallbackFuture<T> {
+    /// Returns a clone of the result if the callback fired, without polling
+    pub fn peek(&self) -> Option<T> {
+        self.state.lock().unwrap().value.clone()
+    }
+}
+
+impl<T> Clone for SharedCallbackFuture<T> {
+    fn clone(&self) -> SharedCallbackFuture<T> {
+        SharedCallbackFuture {
+            state: self.state.clone(),
+            key: self.state.lock().unwrap().next_key(),
+        }
+    }
+}
+
+impl<T> Drop for SharedCallbackFuture<T> {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.wakers.remove(&self.key);
+        }
+    }
+}
+
+impl<T: Clone + Send + 'static> Future for SharedCallbackFuture<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let loader = {
+            let mut state = self.state.lock().unwrap();
+            if let Some(value) = &state.value {
+                return Poll::Ready(value.clone());
+            }
+            // register waker of this clone on every poll: it may have been moved to another task
+            match state.wakers.get_mut(&self.key) {
+                Some(waker) if waker.will_wake(cx.waker()) => {}
+                Some(waker) => *waker = cx.waker().clone(),
+                None => { state.wakers.insert(self.key, cx.waker().clone()); }
+            }
+            state.loader.take()
+        };
+        // in case loader is still present, loader was not yet invoked by any clone: invoke it
+        // without holding the lock, as the callback may be called synchronously
+        if let Some(loader) = loader {
+            let state = self.state.clone();
+            loader(Box::new(move |value| {
+                let wakers = {
+                    let mut state = state.lock().unwrap();
+                    state.value = Some(value);
+                    std::mem::take(&mut state.wakers)
+                };
+                wakers.into_values().for_each(Waker::wake);
+            }));
+            if let Some(value) = &self.state.lock().unwrap().value {
+                return Poll::Ready(value.clone());
+            }
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use futures::executor::block_on;
+    use futures::{join, poll};
+
+    use crate::SharedCallbackFuture;
+
+    #[test]
+    fn test_complete_sync() {
+        let fu = SharedCallbackFuture::new(move |complete| {
+            complete(42);
+        });
+
+        assert_eq!(block_on(fu.clone()), 42);
+        assert_eq!(block_on(fu), 42);
+    }
+
+    #[test]
+    fn test_complete_async() {
+        let fu = SharedCallbackFuture::new(move |complete| {
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                complete("Hello".to_string());
+            });
+        });
+
+        let (r1, r2, r3) = block_on(async { join!(fu.clone(), fu.clone(), fu) });
+        assert_eq!([r1, r2, r3].concat(), "HelloHelloHello");
+    }
+
+    #[test]
+    fn test_loader_invoked_once() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let loader_loads = loads.clone();
+        let fu = SharedCallbackFuture::new(move |complete| {
+            loader_loads.fetch_add(1, Ordering::SeqCst);
+            thread::spawn(move || { complete(42); });
+        });
+
+        let waiters = (0..8).map(|_| {
+            let fu = fu.clone();
+            thread::spawn(move || block_on(fu))
+        }).collect::<Vec<_>>();
+
+        for waiter in waiters {
+            assert_eq!(waiter.join().unwrap(), 42);
+        }
+        assert_eq!(block_on(fu), 42);
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_wakes_every_clone() {
+        let (tx, rx) = mpsc::channel();
+        let mut fu1 = SharedCallbackFuture::new(move |complete| {
+            tx.send(complete).unwrap();
+        });
+        let mut fu2 = fu1.clone();
+
+        assert!(block_on(async { poll!(&mut fu1) }).is_pending());
+        assert!(block_on(async { poll!(&mut fu2) }).is_pending());
+
+        let waiter1 = thread::spawn(move || block_on(fu1));
+        let waiter2 = thread::spawn(move || block_on(fu2));
+        thread::sleep(Duration::from_millis(50));
+        rx.recv().unwrap()(42);
+
+        assert_eq!(waiter1.join().unwrap(), 42);
+        assert_eq!(waiter2.join().unwrap(), 42);
+    }
+
+    #[test]
+    fn test_clone_after_completion() {
+        let fu = SharedCallbackFuture::new(move |complete| {
+            complete(42);
+        });
+
+        assert!(!fu.is_completed());
+        assert_eq!(block_on(fu.clone()), 42);
+        assert!(fu.is_completed());
+        assert_eq!(fu.peek(), Some(42));
+        assert_eq!(block_on(fu.clone()), 42);
+    }
+
+    #[test]
+    fn test_dropped_clone() {
+        let (tx, rx) = mpsc::channel();
+        let fu = SharedCallbackFuture::new(move |complete| {
+            tx.send(complete).unwrap();
+        });
+        let mut dropped = fu.clone();
+
+        assert!(block_on(async { poll!(&mut dropped) }).is_pending());
+        drop(dropped);
+
+        let waiter = thread::spawn(move || block_on(fu));
+        rx.recv().unwrap()(42);
+        assert_eq!(waiter.join().unwrap(), 42);
+    }
+
+    #[test]
+    fn test_ready() {
+        let fu = SharedCallbackFuture::ready(42);
+
+        assert_eq!(block_on(fu.clone()), 42);
+        assert_eq!(block_on(fu), 42);
+    }
+}
@@ -0,0 +1,249 @@
+use std::hash::Hash;
+
+use crate::{CachePolicy, CallbackCache, SharedCallbackFuture};
+
+/// Request coalescing for callback-based operations.
+///
+/// The first caller of a key starts the operation with its loader; callers of the same key
+/// arriving while it is in flight join it instead of starting another one. The key is released
+/// as soon as the callback fires, so the next caller starts a new operation.
+/// Cloning shares the in-flight operations.
+pub struct SingleFlight<K, T> {
+    calls: CallbackCache<K, T>,
+}
+
+impl<K, T> Clone for SingleFlight<K, T> {
+    fn clone(&self) -> SingleFlight<K, T> {
+        SingleFlight { calls: self.calls.clone() }
+    }
+}
+
+impl<K: Hash + Eq + Clone + Send + 'static, T: Clone + Send + 'static> Default for SingleFlight<K, T> {
+    fn default() -> SingleFlight<K, T> {
+        SingleFlight::new()
+    }
+}
+
+impl<K: Hash + Eq + Clone + Send + 'static, T: Clone + Send + 'static> SingleFlight<K, T> {
+    /// Creates a new SingleFlight
+    pub fn new() -> SingleFlight<K, T> {
+        SingleFlight {
+            // a cache which never keeps completed results
+            calls: CallbackCache::with_filter(CachePolicy::default(), |_| false),
+        }
+    }
+
+    /// Returns a future resolving to the result of the in-flight operation for the key,
+    /// starting a new one with the given loader if there is none
+    ///
+    /// # Examples
+    /// ```
+    /// use callback_future::SingleFlight;
+    /// use futures::executor::block_on;
+    /// use futures::join;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// use std::thread;
+    ///
+    /// let requests = Arc::new(AtomicUsize::new(0));
+    /// let fetch = |group: &SingleFlight<&'static str, String>| {
+    ///     let requests = requests.clone();
+    ///     group.call("config", move |complete| {
+    ///         requests.fetch_add(1, Ordering::SeqCst);
+    ///         thread::spawn(move || complete("Config".to_string()));
+    ///     })
+    /// };
+    ///
+    /// let group = SingleFlight::new();
+    /// let (c1, c2) = block_on(async { join!(fetch(&group), fetch(&group)) });
+    /// assert_eq!((c1.as_str(), c2.as_str()), ("Config", "Config"));
+    /// assert_eq!(requests.load(Ordering::SeqCst), 1);
+    /// ```
+    pub fn call(&self, key: K, loader: impl FnOnce(Box<dyn FnOnce(T) + Send + 'static>) + Send + 'static)
+                -> SharedCallbackFuture<T> {
+        self.calls.get(key, loader)
+    }
+
+    /// Detaches the in-flight operation for the key, so the next caller starts a new one
+    pub fn forget(&self, key: &K) {
+        self.calls.invalidate(key);
+    }
+
+    /// Returns the number of keys with an in-flight operation
+    pub fn in_flight(&self) -> usize {
+        self.calls.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc;
+    use std::thread;
+
+    use futures::executor::block_on;
+    use futures::future::join_all;
+
+    use crate::SingleFlight;
+
+    #[test]
+    fn test_join_in_flight() {
+        let flights = Arc::new(AtomicUsize::new(0));
+        let (tx, rx) = mpsc::channel();
+        let group = SingleFlight::new();
+        let calls = (0..8).map(|_| {
+            let flights = flights.clone();
+            let tx = tx.clone();
+            group.call("key", move |complete| {
+                flights.fetch_add(1, Ordering::SeqCst);
+                tx.send(complete).unwrap();
+            })
+        }).collect::<Vec<_>>();
+        assert_eq!(group.in_flight(), 1);
+
+        let waiter = thread::spawn(move || block_on(join_all(calls)));
+        rx.recv().unwrap()(42);
+
+        assert_eq!(waiter.join().unwrap(), vec![42; 8]);
+        assert_eq!(flights.load(Ordering::SeqCst), 1);
+        assert_eq!(group.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_cleared_on_completion() {
+        let flights = Arc::new(AtomicUsize::new(0));
+        let group = SingleFlight::new();
+        let call = |group: &SingleFlight<&'static str, usize>| {
+            let flights = flights.clone();
+            group.call("key", move |complete| {
+                complete(flights.fetch_add(1, Ordering::SeqCst));
+            })
+        };
+
+        assert_eq!(block_on(call(&group)), 0);
+        assert_eq!(group.in_flight(), 0);
+        assert_eq!(block_on(call(&group)), 1);
+        assert_eq!(group.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_cleared_before_wake() {
+        let (tx, rx) = mpsc::channel();
+        let group = SingleFlight::new();
+        let call = group.call("key", move |complete| tx.send(complete).unwrap());
+
+        let waiter_group = group.clone();
+        let waiter = thread::spawn(move || {
+            let value = block_on(call);
+            (value, waiter_group.in_flight())
+        });
+        rx.recv().unwrap()(42);
+
+        assert_eq!(waiter.join().unwrap(), (42, 0));
+    }
+
+    #[test]
+    fn test_distinct_keys() {
+        let group = SingleFlight::new();
+        let a = group.call("a", move |complete| complete(1));
+        let b = group.call("b", move |complete| complete(2));
+
+        assert_eq!(group.in_flight(), 2);
+        assert_eq!(block_on(join_all(vec![a, b])), vec![1, 2]);
+        assert_eq!(group.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_forget() {
+        let (tx, rx) = mpsc::channel();
+        let group = SingleFlight::new();
+        let first_tx = tx.clone();
+        let first = group.call("key", move |complete| first_tx.send(complete).unwrap());
+        group.forget(&"key");
+        let second = group.call("key", move |complete| tx.send(complete).unwrap());
+
+        let waiter = thread::spawn(move || block_on(join_all(vec![first, second])));
+        let complete_first = rx.recv().unwrap();
+        let complete_second = rx.recv().unwrap();
+        complete_second(2);
+        complete_first(1);
+
+        let mut results = waiter.join().unwrap();
+        results.sort_unstable();
+        assert_eq!(results, vec![1, 2]);
+        assert_eq!(group.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_stress_completion_and_joiners() {
+        const THREADS: usize = 8;
+        const CALLS: usize = 500;
+
+        let flights = Arc::new(AtomicUsize::new(0));
+        let group = SingleFlight::new();
+        let callers = (0..THREADS).map(|thread_index| {
+            let flights = flights.clone();
+            let group = group.clone();
+            thread::spawn(move || {
+                for i in 0..CALLS {
+                    let loader_flights = flights.clone();
+                    let call = group.call(i % 4, move |complete| {
+                        let flight = loader_flights.fetch_add(1, Ordering::SeqCst);
+                        // race completion on another thread against new joiners
+                        if (thread_index + i) % 2 == 0 {
+                            complete(flight);
+                        } else {
+                            thread::spawn(move || complete(flight));
+                        }
+                    });
+                    let flight = block_on(call);
+                    assert!(flight < flights.load(Ordering::SeqCst));
+                }
+            })
+        }).collect::<Vec<_>>();
+
+        for caller in callers {
+            caller.join().unwrap();
+        }
+        let flights = flights.load(Ordering::SeqCst);
+        assert!((1..=THREADS * CALLS).contains(&flights));
+        assert_eq!(group.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_stress_joiners_share_flight() {
+        const ROUNDS: usize = 200;
+        const JOINERS: usize = 8;
+
+        let group = SingleFlight::new();
+        for round in 0..ROUNDS {
+            let flights = Arc::new(AtomicUsize::new(0));
+            let (tx, rx) = mpsc::channel();
+            let joiners = (0..JOINERS).map(|_| {
+                let group = group.clone();
+                let flights = flights.clone();
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    let call = group.call("key", move |complete| {
+                        flights.fetch_add(1, Ordering::SeqCst);
+                        tx.send(complete).unwrap();
+                    });
+                    block_on(call)
+                })
+            }).collect::<Vec<_>>();
+            drop(tx);
+            // complete every flight started in this round; joiners arriving after completion
+            // start new flights, which are completed as well
+            for complete in rx {
+                complete(round);
+            }
+
+            for joiner in joiners {
+                assert_eq!(joiner.join().unwrap(), round);
+            }
+            assert!(flights.load(Ordering::SeqCst) >= 1);
+            assert_eq!(group.in_flight(), 0);
+        }
+    }
+}
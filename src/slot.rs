@@ -0,0 +1,229 @@
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU8, Ordering};
+use core::task::{Poll, Waker};
+
+/// No value, no waker
+const EMPTY: u8 = 0;
+/// Consumer is storing its waker
+const REGISTERING: u8 = 1;
+/// Waker is stored, waiting for value
+const WAITING: u8 = 2;
+/// Value is stored
+const COMPLETE: u8 = 3;
+/// Value was taken by consumer
+const TAKEN: u8 = 4;
+
+/// Completion slot shared between a single producer (the completion callback)
+/// and a single consumer (the future).
+///
+/// Holds the value and the waker of the latest poll behind a single atomic state:
+/// `EMPTY -> REGISTERING <-> WAITING -> COMPLETE -> TAKEN`. Producer never waits for consumer
+/// and vice versa, both sides finish in a bounded number of steps, so it is safe to complete
+/// from an interrupt context.
+pub(crate) struct Slot<T> {
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+    // owned by consumer while REGISTERING, by producer after it swapped WAITING to COMPLETE
+    waker: UnsafeCell<Option<Waker>>,
+}
+
+unsafe impl<T: Send> Send for Slot<T> {}
+unsafe impl<T: Send> Sync for Slot<T> {}
+
+impl<T> Slot<T> {
+    pub(crate) fn new(value: Option<T>) -> Slot<T> {
+        let (state, value) = match value {
+            Some(value) => (COMPLETE, MaybeUninit::new(value)),
+            None => (EMPTY, MaybeUninit::uninit()),
+        };
+        Slot {
+            state: AtomicU8::new(state),
+            value: UnsafeCell::new(value),
+            waker: UnsafeCell::new(None),
+        }
+    }
+
+    /// Stores the value and wakes the consumer
+    ///
+    /// # Safety
+    /// Must be called at most once, and not on a slot created with a value.
+    pub(crate) unsafe fn complete(&self, value: T) {
+        // consumer doesn't access value before it observes COMPLETE
+        (*self.value.get()).as_mut_ptr().write(value);
+        // if consumer is registering, it will observe COMPLETE itself
+        if self.state.swap(COMPLETE, Ordering::AcqRel) == WAITING {
+            if let Some(waker) = (*self.waker.get()).take() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Takes the value if it is stored, otherwise registers the waker to be woken upon completion
+    ///
+    /// # Panics
+    /// Panics if the value was already taken.
+    pub(crate) fn poll(&self, waker: &Waker) -> Poll<T> {
+        let state = self.state.load(Ordering::Acquire);
+        match state {
+            EMPTY | WAITING => {
+                if self.state.compare_exchange(state, REGISTERING, Ordering::Acquire, Ordering::Acquire).is_err() {
+                    // the only concurrent transition is to COMPLETE
+                    return Poll::Ready(self.take_completed());
+                }
+                let stored = unsafe { &mut *self.waker.get() };
+                match stored {
+                    Some(stored) if stored.will_wake(waker) => {}
+                    _ => *stored = Some(waker.clone()),
+                }
+                match self.state.compare_exchange(REGISTERING, WAITING, Ordering::AcqRel, Ordering::Acquire) {
+                    Ok(_) => Poll::Pending,
+                    Err(_) => {
+                        // completed while registering: producer didn't touch the waker
+                        drop(stored.take());
+                        Poll::Ready(self.take_completed())
+                    }
+                }
+            }
+            COMPLETE => Poll::Ready(self.take_completed()),
+            _ => panic!("CallbackFuture polled after completion"),
+        }
+    }
+
+    /// Takes the value if it is stored
+    pub(crate) fn try_take(&self) -> Option<T> {
+        match self.state.load(Ordering::Acquire) {
+            COMPLETE => Some(self.take_completed()),
+            _ => None,
+        }
+    }
+
+    /// Whether the value was stored, regardless of whether it was taken
+    pub(crate) fn is_complete(&self) -> bool {
+        matches!(self.state.load(Ordering::Acquire), COMPLETE | TAKEN)
+    }
+
+    fn take_completed(&self) -> T {
+        // only consumer moves state from COMPLETE
+        self.state.store(TAKEN, Ordering::Relaxed);
+        unsafe { (*self.value.get()).as_ptr().read() }
+    }
+}
+
+impl<T> Drop for Slot<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() == COMPLETE {
+            unsafe { self.value.get_mut().as_mut_ptr().drop_in_place() };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Poll;
+    use std::thread;
+
+    use futures::task::{noop_waker_ref, waker};
+
+    use crate::slot::Slot;
+    use crate::test_util::{CountingWaker, DropCounter};
+
+    #[test]
+    fn test_complete_before_poll() {
+        let slot = Slot::new(None);
+        assert!(!slot.is_complete());
+
+        unsafe { slot.complete(42) };
+        assert!(slot.is_complete());
+
+        assert_eq!(slot.poll(noop_waker_ref()), Poll::Ready(42));
+        assert!(slot.is_complete());
+        assert_eq!(slot.try_take(), None);
+    }
+
+    #[test]
+    fn test_complete_after_poll() {
+        let counter = Arc::new(CountingWaker::default());
+        let waker = waker(counter.clone());
+        let slot = Slot::new(None);
+
+        assert_eq!(slot.poll(&waker), Poll::Pending);
+        assert_eq!(slot.poll(&waker), Poll::Pending);
+        unsafe { slot.complete(42) };
+
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        assert_eq!(slot.poll(&waker), Poll::Ready(42));
+    }
+
+    #[test]
+    fn test_ready() {
+        let slot = Slot::new(Some(42));
+        assert!(slot.is_complete());
+        assert_eq!(slot.poll(noop_waker_ref()), Poll::Ready(42));
+    }
+
+    #[test]
+    fn test_try_take() {
+        let slot = Slot::new(None);
+        assert_eq!(slot.try_take(), None);
+        assert_eq!(slot.poll(noop_waker_ref()), Poll::Pending);
+        assert_eq!(slot.try_take(), None);
+
+        unsafe { slot.complete(42) };
+        assert_eq!(slot.try_take(), Some(42));
+        assert_eq!(slot.try_take(), None);
+        assert!(slot.is_complete());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_poll_after_take() {
+        let slot = Slot::new(Some(42));
+        assert_eq!(slot.poll(noop_waker_ref()), Poll::Ready(42));
+        let _ = slot.poll(noop_waker_ref());
+    }
+
+    #[test]
+    fn test_drop_value() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let slot = Slot::new(None);
+        unsafe { slot.complete(DropCounter(drops.clone())) };
+        drop(slot);
+        assert_eq!(drops.load(Ordering::SeqCst), 1);
+
+        let slot = Slot::new(None);
+        unsafe { slot.complete(DropCounter(drops.clone())) };
+        drop(slot.poll(noop_waker_ref()));
+        drop(slot);
+        assert_eq!(drops.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_concurrent_complete_poll() {
+        for _ in 0..1000 {
+            let counter = Arc::new(CountingWaker::default());
+            let waker = waker(counter.clone());
+            let slot = Arc::new(Slot::new(None));
+            let producer = slot.clone();
+            let handle = thread::spawn(move || unsafe { producer.complete(42) });
+            let value = match slot.poll(&waker) {
+                Poll::Ready(value) => value,
+                Poll::Pending => {
+                    // completion must not be lost: wait for wake-up, then value must be ready
+                    while counter.0.load(Ordering::SeqCst) == 0 {
+                        thread::yield_now();
+                    }
+                    match slot.poll(&waker) {
+                        Poll::Ready(value) => value,
+                        Poll::Pending => panic!("woken before completion"),
+                    }
+                }
+            };
+            handle.join().unwrap();
+            assert_eq!(value, 42);
+            assert!(counter.0.load(Ordering::SeqCst) <= 1);
+        }
+    }
+}
@@ -0,0 +1,473 @@
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex};
+
+use futures::Stream;
+use futures::task::{AtomicWaker, Context, Poll};
+
+type StreamLoader<T> = Box<dyn FnOnce(Emitter<T>) + Send + 'static>;
+
+/// Buffering policy of a CallbackStream, applied when items are emitted faster than polled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BufferPolicy {
+    /// Buffers all items
+    #[default]
+    Unbounded,
+    /// Buffers up to given number of items, dropping the oldest buffered item when full
+    DropOldest(usize),
+    /// Buffers up to given number of items, dropping the emitted item when full
+    DropNewest(usize),
+    /// Keeps only the latest item, dropping the buffered one
+    KeepLatest,
+    /// Buffers up to given number of items, blocking the emitting thread when full.
+    /// Must not be used when items are emitted from the polling thread.
+    Block(usize),
+}
+
+/// State shared between a CallbackStream and its emitters.
+struct StreamShared<T> {
+    state: Mutex<StreamState<T>>,
+    policy: BufferPolicy,
+    space: Condvar, // notified when buffer has free space or stream is ended
+    waker: AtomicWaker,
+}
+
+struct StreamState<T> {
+    items: VecDeque<T>,
+    ended: bool,
+    emitters: usize,
+    dropped: u64,
+}
+
+impl<T> StreamShared<T> {
+    fn end(&self) {
+        self.state.lock().unwrap().ended = true;
+        self.space.notify_all();
+        self.waker.wake();
+    }
+}
+
+/// Emitting handle passed to the loader of a CallbackStream.
+///
+/// Can be cloned and shared between callbacks. The stream ends once `end` is called
+/// on any clone, or once all clones are dropped.
+pub struct Emitter<T> {
+    shared: Arc<StreamShared<T>>,
+}
+
+impl<T> Emitter<T> {
+    /// Buffers an item for the stream according to its `BufferPolicy` and wakes the consumer.
+    /// Items emitted after the end of the stream, or after the stream is dropped, are ignored.
+    pub fn emit(&self, value: T) {
+        {
+            let mut state = self.shared.state.lock().unwrap();
+            loop {
+                if state.ended {
+                    return;
+                }
+                match self.shared.policy {
+                    BufferPolicy::Unbounded => break,
+                    BufferPolicy::DropOldest(capacity) => {
+                        if state.items.len() >= capacity {
+                            state.items.pop_front();
+                            state.dropped += 1;
+                        }
+                        break;
+                    }
+                    BufferPolicy::DropNewest(capacity) => {
+                        if state.items.len() >= capacity {
+                            state.dropped += 1;
+                            return;
+                        }
+                        break;
+                    }
+                    BufferPolicy::KeepLatest => {
+                        state.dropped += state.items.len() as u64;
+                        state.items.clear();
+                        break;
+                    }
+                    BufferPolicy::Block(capacity) => {
+                        if state.items.len() < capacity {
+                            break;
+                        }
+                        state = self.shared.space.wait(state).unwrap();
+                    }
+                }
+            }
+            state.items.push_back(value);
+        }
+        self.shared.waker.wake();
+    }
+
+    /// Returns the number of items dropped so far by the stream's `BufferPolicy`
+    pub fn dropped(&self) -> u64 {
+        self.shared.state.lock().unwrap().dropped
+    }
+
+    /// Ends the stream: it yields remaining buffered items and then `None`
+    pub fn end(&self) {
+        self.shared.end();
+    }
+}
+
+impl<T> Clone for Emitter<T> {
+    fn clone(&self) -> Emitter<T> {
+        self.shared.state.lock().unwrap().emitters += 1;
+        Emitter { shared: self.shared.clone() }
+    }
+}
+
+impl<T> Drop for Emitter<T> {
+    fn drop(&mut self) {
+        let last = {
+            let mut state = self.shared.state.lock().unwrap();
+            state.emitters -= 1;
+            state.emitters == 0
+        };
+        if last {
+            self.shared.end();
+        }
+    }
+}
+
+/// An adapter between repeatedly called callbacks and streams.
+///
+/// Calls loader upon first `Stream::poll_next` call, passing it an `Emitter`;
+/// buffers emitted items until they are polled according to the `BufferPolicy`.
+/// Dropping the stream ends it, so subsequent items are ignored.
+pub struct CallbackStream<T> {
+    loader: Option<StreamLoader<T>>,
+    shared: Arc<StreamShared<T>>,
+}
+
+impl<T> CallbackStream<T> {
+    /// Creates a new CallbackStream
+    ///
+    /// # Examples
+    /// ```
+    /// use callback_future::CallbackStream;
+    /// use futures::executor::block_on_stream;
+    /// use std::thread;
+    ///
+    /// let stream = CallbackStream::new(|emitter| {
+    ///     // subscribe with callback here, call `emit` upon each callback, e.g.:
+    ///     thread::spawn(move || {
+    ///         for progress in 1..=3 {
+    ///             emitter.emit(progress);
+    ///         }
+    ///         emitter.end();
+    ///     });
+    /// });
+    /// assert_eq!(block_on_stream(stream).collect::<Vec<_>>(), vec![1, 2, 3]);
+    /// ```
+    pub fn new(loader: impl FnOnce(Emitter<T>) + Send + 'static) -> CallbackStream<T> {
+        CallbackStream::with_policy(BufferPolicy::Unbounded, loader)
+    }
+
+    /// Creates a new CallbackStream with the given buffering policy
+    ///
+    /// # Panics
+    /// Panics if policy capacity is zero.
+    ///
+    /// # Examples
+    /// ```
+    /// use callback_future::{BufferPolicy, CallbackStream};
+    /// use futures::executor::block_on_stream;
+    ///
+    /// let stream = CallbackStream::with_policy(BufferPolicy::DropOldest(2), |emitter| {
+    ///     for reading in 1..=5 {
+    ///         emitter.emit(reading);
+    ///     }
+    ///     emitter.end();
+    /// });
+    /// assert_eq!(block_on_stream(stream).collect::<Vec<_>>(), vec![4, 5]);
+    /// ```
+    pub fn with_policy(policy: BufferPolicy, loader: impl FnOnce(Emitter<T>) + Send + 'static)
+                       -> CallbackStream<T> {
+        match policy {
+            BufferPolicy::DropOldest(capacity)
+            | BufferPolicy::DropNewest(capacity)
+            | BufferPolicy::Block(capacity) => assert!(capacity > 0, "buffer capacity must be positive"),
+            BufferPolicy::Unbounded | BufferPolicy::KeepLatest => {}
+        }
+        CallbackStream {
+            loader: Some(Box::new(loader)),
+            shared: Arc::new(StreamShared {
+                state: Mutex::new(StreamState {
+                    items: VecDeque::new(),
+                    ended: false,
+                    emitters: 0,
+                    dropped: 0,
+                }),
+                policy,
+                space: Condvar::new(),
+                waker: AtomicWaker::new(),
+            }),
+        }
+    }
+
+    /// Returns the number of items dropped so far by the `BufferPolicy`
+    pub fn dropped(&self) -> u64 {
+        self.shared.state.lock().unwrap().dropped
+    }
+}
+
+impl<T> Drop for CallbackStream<T> {
+    fn drop(&mut self) {
+        // release emitters blocked on full buffer
+        self.shared.end();
+    }
+}
+
+impl<T: Send + 'static> Stream for CallbackStream<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let self_mut = self.get_mut();
+        self_mut.shared.waker.register(cx.waker());
+        // in case loader is still present, loader was not yet invoked: invoke it
+        if let Some(loader) = self_mut.loader.take() {
+            self_mut.shared.state.lock().unwrap().emitters = 1;
+            loader(Emitter { shared: self_mut.shared.clone() });
+        }
+        let mut state = self_mut.shared.state.lock().unwrap();
+        match state.items.pop_front() {
+            Some(value) => {
+                self_mut.shared.space.notify_one();
+                Poll::Ready(Some(value))
+            }
+            None if state.ended => Poll::Ready(None),
+            None => Poll::Pending, // we haven't received next callback yet
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use futures::executor::{block_on, block_on_stream};
+    use futures::{Stream, StreamExt};
+    use futures::task::{Context, noop_waker_ref};
+
+    use crate::{BufferPolicy, CallbackStream, Emitter};
+
+    const PRODUCERS: usize = 4;
+    const ITEMS: usize = 1000;
+
+    /// Emits `ITEMS` items from each of `PRODUCERS` threads, ends the stream after all of them finish
+    fn produce_concurrently(emitter: Emitter<usize>) {
+        let handles = (0..PRODUCERS).map(|producer| {
+            let emitter = emitter.clone();
+            thread::spawn(move || {
+                for i in 0..ITEMS {
+                    emitter.emit(producer * ITEMS + i);
+                }
+            })
+        }).collect::<Vec<_>>();
+        handles.into_iter().for_each(|handle| handle.join().unwrap());
+        emitter.end();
+    }
+
+    #[test]
+    fn test_emit_sync() {
+        let st = CallbackStream::new(move |emitter| {
+            emitter.emit(1);
+            emitter.emit(2);
+            emitter.end();
+        });
+
+        assert_eq!(block_on_stream(st).collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_emit_async() {
+        let st = CallbackStream::new(move |emitter| {
+            thread::spawn(move || {
+                for i in 0..10 {
+                    thread::sleep(Duration::from_millis(10));
+                    emitter.emit(i);
+                }
+                emitter.end();
+            });
+        });
+
+        assert_eq!(block_on_stream(st).collect::<Vec<_>>(), (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_cloned_emitters() {
+        let st = CallbackStream::new(move |emitter| {
+            let handles = (0..4).map(|_| {
+                let emitter = emitter.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        emitter.emit(1);
+                    }
+                })
+            }).collect::<Vec<_>>();
+            thread::spawn(move || {
+                handles.into_iter().for_each(|handle| handle.join().unwrap());
+                emitter.end();
+            });
+        });
+
+        assert_eq!(block_on(st.fold(0, |sum, i| async move { sum + i })), 400);
+    }
+
+    #[test]
+    fn test_emit_after_end() {
+        let st = CallbackStream::new(move |emitter| {
+            emitter.emit(1);
+            emitter.end();
+            emitter.emit(2);
+        });
+
+        assert_eq!(block_on_stream(st).collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn test_end_on_drop() {
+        let st = CallbackStream::new(move |emitter| {
+            let emitter2 = emitter.clone();
+            thread::spawn(move || { emitter.emit(1); });
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                emitter2.emit(2);
+            });
+        });
+
+        assert_eq!(block_on_stream(st).collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_policy_unbounded() {
+        let mut st = CallbackStream::with_policy(BufferPolicy::Unbounded, produce_concurrently);
+
+        let mut items = block_on((&mut st).collect::<Vec<_>>());
+        items.sort_unstable();
+        assert_eq!(items, (0..PRODUCERS * ITEMS).collect::<Vec<_>>());
+        assert_eq!(st.dropped(), 0);
+    }
+
+    #[test]
+    fn test_policy_drop_oldest() {
+        let mut st = CallbackStream::with_policy(BufferPolicy::DropOldest(10), produce_concurrently);
+
+        // all items are emitted before the first item is polled
+        let items = block_on((&mut st).collect::<Vec<_>>());
+        assert_eq!(items.len(), 10);
+        assert_eq!(st.dropped(), (PRODUCERS * ITEMS - 10) as u64);
+    }
+
+    #[test]
+    fn test_policy_drop_oldest_order() {
+        let st = CallbackStream::with_policy(BufferPolicy::DropOldest(3), |emitter| {
+            (0..10).for_each(|i| emitter.emit(i));
+        });
+
+        assert_eq!(block_on_stream(st).collect::<Vec<_>>(), vec![7, 8, 9]);
+    }
+
+    #[test]
+    fn test_policy_drop_newest() {
+        let mut st = CallbackStream::with_policy(BufferPolicy::DropNewest(10), produce_concurrently);
+
+        let items = block_on((&mut st).collect::<Vec<_>>());
+        assert_eq!(items.len(), 10);
+        assert_eq!(st.dropped(), (PRODUCERS * ITEMS - 10) as u64);
+    }
+
+    #[test]
+    fn test_policy_drop_newest_order() {
+        let st = CallbackStream::with_policy(BufferPolicy::DropNewest(3), |emitter| {
+            (0..10).for_each(|i| emitter.emit(i));
+        });
+
+        assert_eq!(block_on_stream(st).collect::<Vec<_>>(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_policy_keep_latest() {
+        let mut st = CallbackStream::with_policy(BufferPolicy::KeepLatest, produce_concurrently);
+
+        let items = block_on((&mut st).collect::<Vec<_>>());
+        assert_eq!(items.len(), 1);
+        assert_eq!(st.dropped(), (PRODUCERS * ITEMS - 1) as u64);
+    }
+
+    #[test]
+    fn test_policy_keep_latest_order() {
+        let st = CallbackStream::with_policy(BufferPolicy::KeepLatest, |emitter| {
+            (0..10).for_each(|i| emitter.emit(i));
+        });
+
+        assert_eq!(block_on_stream(st).collect::<Vec<_>>(), vec![9]);
+    }
+
+    #[test]
+    fn test_policy_block() {
+        let mut st = CallbackStream::with_policy(BufferPolicy::Block(10), |emitter| {
+            thread::spawn(move || produce_concurrently(emitter));
+        });
+
+        let mut items = block_on((&mut st).collect::<Vec<_>>());
+        items.sort_unstable();
+        assert_eq!(items, (0..PRODUCERS * ITEMS).collect::<Vec<_>>());
+        assert_eq!(st.dropped(), 0);
+    }
+
+    #[test]
+    fn test_policy_block_waits_for_consumer() {
+        let (start_tx, start_rx) = mpsc::channel();
+        let (full_tx, full_rx) = mpsc::channel();
+        let finished = Arc::new(AtomicBool::new(false));
+        let producer_finished = finished.clone();
+        let mut st = CallbackStream::with_policy(BufferPolicy::Block(2), move |emitter| {
+            thread::spawn(move || {
+                start_rx.recv().unwrap();
+                emitter.emit(0);
+                emitter.emit(1);
+                full_tx.send(()).unwrap();
+                emitter.emit(2);
+                producer_finished.store(true, Ordering::SeqCst);
+            });
+        });
+
+        // invoke loader
+        assert!(Pin::new(&mut st).poll_next(&mut Context::from_waker(noop_waker_ref())).is_pending());
+        start_tx.send(()).unwrap();
+        full_rx.recv().unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert!(!finished.load(Ordering::SeqCst));
+
+        assert_eq!(block_on((&mut st).collect::<Vec<_>>()), vec![0, 1, 2]);
+        assert!(finished.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_policy_block_released_on_drop() {
+        let (tx, rx) = mpsc::channel();
+        let mut st = CallbackStream::with_policy(BufferPolicy::Block(1), move |emitter| {
+            thread::spawn(move || {
+                (0..3).for_each(|i| emitter.emit(i));
+                tx.send(()).unwrap();
+            });
+        });
+
+        assert_eq!(block_on(st.next()), Some(0));
+        drop(st);
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_policy_zero_capacity() {
+        CallbackStream::<()>::with_policy(BufferPolicy::DropOldest(0), drop);
+    }
+}
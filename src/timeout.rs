@@ -0,0 +1,384 @@
+use alloc::boxed::Box;
+use core::fmt;
+use core::pin::Pin;
+use core::time::Duration;
+
+use futures::Future;
+use futures::task::{Context, Poll};
+
+use crate::{BoxLoader, CallbackFuture, Load};
+#[cfg(feature = "std")]
+pub use thread_timer::ThreadTimer;
+
+/// Future which completes after a delay, produced by a `Timer`.
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// Source of delays for timeouts.
+///
+/// Allows plugging in a runtime timer; `ThreadTimer` is used by default.
+pub trait Timer {
+    /// Returns a future which completes after the given duration
+    fn sleep(&self, duration: Duration) -> Sleep;
+}
+
+/// Error returned by a `Timeout` when the callback didn't fire in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("callback was not called in time")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Elapsed {}
+
+/// Future returned by `CallbackFuture::with_timeout`.
+///
+/// Resolves to `Err(Elapsed)` if the callback didn't fire before the timer; in that case
+/// the inner future is dropped, which runs its cancellation handle, and a late callback is ignored.
+pub struct Timeout<T, L = BoxLoader<T>> {
+    future: Option<CallbackFuture<T, L>>,
+    sleep: Sleep,
+}
+
+impl<T, L> CallbackFuture<T, L> {
+    /// Limits the time to wait for the callback, using the built-in `ThreadTimer`
+    ///
+    /// The time is counted from this call, not from the first poll.
+    ///
+    /// # Examples
+    /// ```
+    /// use callback_future::{CallbackFuture, Elapsed};
+    /// use futures::executor::block_on;
+    /// use std::time::Duration;
+    ///
+    /// let future = CallbackFuture::<()>::new(|complete| {
+    ///     // callback never arrives
+    ///     std::mem::forget(complete);
+    /// });
+    /// assert_eq!(block_on(future.with_timeout(Duration::from_millis(10))), Err(Elapsed));
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn with_timeout(self, timeout: Duration) -> Timeout<T, L> {
+        self.with_timeout_on(&ThreadTimer, timeout)
+    }
+
+    /// Limits the time to wait for the callback, using the given timer
+    pub fn with_timeout_on(self, timer: &impl Timer, timeout: Duration) -> Timeout<T, L> {
+        Timeout {
+            future: Some(self),
+            sleep: timer.sleep(timeout),
+        }
+    }
+}
+
+impl<T, L: Load<T>> Future for Timeout<T, L> {
+    type Output = Result<T, Elapsed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let self_mut = self.get_mut();
+        let future = self_mut.future.as_mut().expect("Timeout polled after completion");
+        if let Poll::Ready(value) = Pin::new(future).poll(cx) {
+            self_mut.future = None;
+            return Poll::Ready(Ok(value));
+        }
+        match self_mut.sleep.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                // cancel the pending operation
+                self_mut.future = None;
+                Poll::Ready(Err(Elapsed))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+mod thread_timer {
+    use std::collections::BTreeMap;
+    use std::pin::Pin;
+    use std::sync::{Arc, Condvar, Mutex, OnceLock};
+    use std::sync::atomic::{self, AtomicBool};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use futures::{future, Future};
+    use futures::task::{AtomicWaker, Context, Poll};
+
+    use super::{Sleep, Timer};
+
+    /// Timer backed by a single background thread, started on first use.
+    ///
+    /// Works with any executor, including `futures::executor::block_on`.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct ThreadTimer;
+
+    impl Timer for ThreadTimer {
+        fn sleep(&self, duration: Duration) -> Sleep {
+            let deadline = match Instant::now().checked_add(duration) {
+                Some(deadline) => deadline,
+                // a deadline too far to represent is never reached
+                None => return Box::pin(future::pending()),
+            };
+            let state = Arc::new(SleepState {
+                fired: AtomicBool::new(false),
+                waker: AtomicWaker::new(),
+            });
+            let key = queue().push(deadline, state.clone());
+            Box::pin(ThreadSleep { state, key })
+        }
+    }
+
+    struct SleepState {
+        fired: AtomicBool,
+        waker: AtomicWaker,
+    }
+
+    /// Deadline of a sleep, made unique by a sequence number
+    type Key = (Instant, u64);
+
+    struct ThreadSleep {
+        state: Arc<SleepState>,
+        key: Key,
+    }
+
+    impl Future for ThreadSleep {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            self.state.waker.register(cx.waker());
+            match self.state.fired.load(atomic::Ordering::Acquire) {
+                true => Poll::Ready(()),
+                false => Poll::Pending,
+            }
+        }
+    }
+
+    impl Drop for ThreadSleep {
+        fn drop(&mut self) {
+            // a fired sleep was already removed by the timer thread
+            if !self.state.fired.load(atomic::Ordering::Acquire) {
+                queue().remove(&self.key);
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct Entries {
+        sleeps: BTreeMap<Key, Arc<SleepState>>,
+        next_id: u64,
+    }
+
+    struct Queue {
+        entries: Mutex<Entries>,
+        changed: Condvar,
+    }
+
+    impl Queue {
+        fn push(&self, deadline: Instant, sleep: Arc<SleepState>) -> Key {
+            let mut entries = self.entries.lock().unwrap();
+            let key = (deadline, entries.next_id);
+            entries.next_id += 1;
+            entries.sleeps.insert(key, sleep);
+            drop(entries);
+            self.changed.notify_one();
+            key
+        }
+
+        fn remove(&self, key: &Key) {
+            self.entries.lock().unwrap().sleeps.remove(key);
+        }
+
+        fn run(&self) {
+            let mut fired = Vec::new();
+            let mut entries = self.entries.lock().unwrap();
+            loop {
+                let now = Instant::now();
+                while let Some(entry) = entries.sleeps.first_entry() {
+                    if entry.key().0 > now {
+                        break;
+                    }
+                    let sleep = entry.remove();
+                    sleep.fired.store(true, atomic::Ordering::Release);
+                    fired.push(sleep);
+                }
+                if !fired.is_empty() {
+                    // wakers run executor code, which may sleep or drop sleeps: not under the lock
+                    drop(entries);
+                    for sleep in fired.drain(..) {
+                        sleep.waker.wake();
+                    }
+                    entries = self.entries.lock().unwrap();
+                    continue;
+                }
+                entries = match entries.sleeps.keys().next() {
+                    Some(&(deadline, _)) => self.changed.wait_timeout(entries, deadline - now).unwrap().0,
+                    None => self.changed.wait(entries).unwrap(),
+                };
+            }
+        }
+    }
+
+    fn queue() -> &'static Queue {
+        static QUEUE: OnceLock<&'static Queue> = OnceLock::new();
+        QUEUE.get_or_init(|| {
+            let queue: &'static Queue = Box::leak(Box::new(Queue {
+                entries: Mutex::new(Entries::default()),
+                changed: Condvar::new(),
+            }));
+            thread::Builder::new()
+                .name("callback-future-timer".into())
+                .spawn(move || queue.run())
+                .expect("failed to spawn timer thread");
+            queue
+        })
+    }
+
+    /// Returns the number of queued sleeps with a deadline after the given instant
+    #[cfg(test)]
+    pub(super) fn queued_after(instant: Instant) -> usize {
+        queue().entries.lock().unwrap().sleeps.keys().filter(|(deadline, _)| *deadline > instant).count()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use futures::executor::block_on;
+    use futures::future;
+    use futures::task::{waker, ArcWake, Context};
+
+    use crate::{CallbackFuture, Elapsed, Sleep, ThreadTimer, Timer};
+    use super::thread_timer;
+
+    #[test]
+    fn test_complete_in_time() {
+        let fu = CallbackFuture::new(move |complete| {
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                complete(42);
+            });
+        });
+
+        assert_eq!(block_on(fu.with_timeout(Duration::from_secs(5))), Ok(42));
+    }
+
+    #[test]
+    fn test_elapsed() {
+        let (tx, rx) = mpsc::channel();
+        let fu = CallbackFuture::new(move |complete| {
+            tx.send(complete).unwrap();
+        });
+
+        let start = Instant::now();
+        assert_eq!(block_on(fu.with_timeout(Duration::from_millis(100))), Err(Elapsed));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        // late callback is ignored
+        rx.recv().unwrap()(42);
+    }
+
+    #[test]
+    fn test_cancel_on_elapsed() {
+        let canceled = Arc::new(AtomicUsize::new(0));
+        let request_canceled = canceled.clone();
+        let fu = CallbackFuture::<i32>::with_cancel(move |_complete| {
+            move || { request_canceled.fetch_add(1, Ordering::SeqCst); }
+        });
+
+        assert_eq!(block_on(fu.with_timeout(Duration::from_millis(10))), Err(Elapsed));
+        assert_eq!(canceled.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_from_fn() {
+        let fu = CallbackFuture::<(), _>::from_fn(drop);
+        assert_eq!(block_on(fu.with_timeout(Duration::from_millis(10))), Err(Elapsed));
+
+        let fu = CallbackFuture::from_fn(|completer| completer.complete(42));
+        assert_eq!(block_on(fu.with_timeout(Duration::from_secs(5))), Ok(42));
+    }
+
+    #[test]
+    fn test_timer_ordering() {
+        let long = CallbackFuture::<()>::new(drop).with_timeout(Duration::from_millis(200));
+        let short = CallbackFuture::<()>::new(drop).with_timeout(Duration::from_millis(10));
+
+        let start = Instant::now();
+        assert_eq!(block_on(short), Err(Elapsed));
+        assert!(start.elapsed() < Duration::from_millis(200));
+        assert_eq!(block_on(long), Err(Elapsed));
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_custom_timer() {
+        struct ImmediateTimer;
+
+        impl Timer for ImmediateTimer {
+            fn sleep(&self, _duration: Duration) -> Sleep {
+                Box::pin(future::ready(()))
+            }
+        }
+
+        let fu = CallbackFuture::<()>::new(drop);
+        assert_eq!(block_on(fu.with_timeout_on(&ImmediateTimer, Duration::from_secs(3600))), Err(Elapsed));
+
+        let fu = CallbackFuture::new(|complete| complete(42));
+        assert_eq!(block_on(fu.with_timeout_on(&ImmediateTimer, Duration::from_secs(3600))), Ok(42));
+    }
+
+    #[test]
+    fn test_dropped_sleep_dequeued() {
+        // no other test sleeps this long
+        let horizon = Instant::now() + Duration::from_secs(3600);
+        let fus = (0..100)
+            .map(|i| CallbackFuture::new(move |complete| complete(i)).with_timeout(Duration::from_secs(7200)))
+            .collect::<Vec<_>>();
+        assert_eq!(thread_timer::queued_after(horizon), 100);
+
+        assert_eq!(block_on(future::join_all(fus)), (0..100).map(Ok).collect::<Vec<_>>());
+        assert_eq!(thread_timer::queued_after(horizon), 0);
+    }
+
+    #[test]
+    fn test_max_duration() {
+        let fu = CallbackFuture::new(|complete| complete(42)).with_timeout(Duration::MAX);
+        assert_eq!(block_on(fu), Ok(42));
+
+        let (tx, rx) = mpsc::channel();
+        let fu = CallbackFuture::new(move |complete| tx.send(complete).unwrap()).with_timeout(Duration::MAX);
+        let waiter = thread::spawn(move || block_on(fu));
+        rx.recv().unwrap()(42);
+        assert_eq!(waiter.join().unwrap(), Ok(42));
+    }
+
+    #[test]
+    fn test_wake_uses_timer() {
+        // a waker which sleeps and drops a sleep, e.g. by dropping a task owning one
+        struct SleepingWaker(Mutex<mpsc::Sender<()>>);
+
+        impl ArcWake for SleepingWaker {
+            fn wake_by_ref(arc_self: &Arc<Self>) {
+                drop(ThreadTimer.sleep(Duration::from_secs(60)));
+                let _ = arc_self.0.lock().unwrap().send(());
+            }
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let waker = waker(Arc::new(SleepingWaker(Mutex::new(tx))));
+        let mut sleep = ThreadTimer.sleep(Duration::from_millis(10));
+        assert!(sleep.as_mut().poll(&mut Context::from_waker(&waker)).is_pending());
+
+        rx.recv_timeout(Duration::from_secs(5)).expect("timer thread deadlocked");
+        // the timer thread is still running
+        block_on(ThreadTimer.sleep(Duration::from_millis(10)));
+    }
+}
@@ -0,0 +1,102 @@
+//! Helpers for the tokio runtime, enabled by the `tokio` feature.
+//!
+//! Everything here spawns onto the current tokio runtime, and panics when used outside of one.
+
+use std::time::Duration;
+
+use ::tokio::sync::oneshot;
+use ::tokio::{task, time};
+use futures::future::{self, Either};
+
+use crate::{CallbackFuture, Canceled, Sleep, Timer};
+
+/// Runs a blocking function on the tokio blocking pool
+///
+/// The function is spawned upon first poll. Resolves to `Err(Canceled)` if the function panics.
+///
+/// # Examples
+/// ```
+/// use callback_future::tokio::spawn_blocking;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let len = spawn_blocking(|| std::fs::read("Cargo.toml").map(|bytes| bytes.len()));
+/// assert!(len.await.unwrap().unwrap() > 0);
+/// # }
+/// ```
+pub fn spawn_blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static)
+                                         -> CallbackFuture<Result<T, Canceled>> {
+    CallbackFuture::try_new(move |complete| {
+        // a panic drops the completion callback, completing with `Err(Canceled)`
+        task::spawn_blocking(move || complete(f()));
+    })
+}
+
+impl<T: Send + 'static> CallbackFuture<T> {
+    /// Drives the future on a spawned task, sending the result to the returned receiver
+    ///
+    /// Dropping the receiver drops the future, which runs its cancellation handle.
+    ///
+    /// # Examples
+    /// ```
+    /// use callback_future::CallbackFuture;
+    /// use std::thread;
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let receiver = CallbackFuture::new(|complete| {
+    ///     thread::spawn(move || complete("Test"));
+    /// }).into_oneshot();
+    /// assert_eq!(receiver.await, Ok("Test"));
+    /// # }
+    /// ```
+    pub fn into_oneshot(self) -> oneshot::Receiver<T> {
+        let (mut sender, receiver) = oneshot::channel();
+        task::spawn(async move {
+            let value = match future::select(self, Box::pin(sender.closed())).await {
+                Either::Left((value, _)) => value,
+                Either::Right(_) => return,
+            };
+            let _ = sender.send(value);
+        });
+        receiver
+    }
+}
+
+/// Resolves to the value sent through the channel, or to `Err(Canceled)` if the sender is dropped.
+///
+/// The receiver is awaited on a task spawned upon first poll; dropping the future aborts the task.
+impl<T: Send + 'static> From<oneshot::Receiver<T>> for CallbackFuture<Result<T, Canceled>> {
+    fn from(receiver: oneshot::Receiver<T>) -> CallbackFuture<Result<T, Canceled>> {
+        CallbackFuture::with_cancel(move |complete| {
+            let handle = task::spawn(async move {
+                complete(receiver.await.map_err(|_| Canceled));
+            });
+            move || handle.abort()
+        })
+    }
+}
+
+/// Timer backed by `tokio::time`, for `CallbackFuture::with_timeout_on` and `retry_on`.
+///
+/// # Examples
+/// ```
+/// use callback_future::{CallbackFuture, Elapsed};
+/// use callback_future::tokio::TokioTimer;
+/// use std::time::Duration;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let future = CallbackFuture::<()>::new(|complete| std::mem::forget(complete));
+/// let result = future.with_timeout_on(&TokioTimer, Duration::from_millis(10)).await;
+/// assert_eq!(result, Err(Elapsed));
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioTimer;
+
+impl Timer for TokioTimer {
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(time::sleep(duration))
+    }
+}
@@ -0,0 +1,158 @@
+use core::pin::Pin;
+
+use futures::Future;
+use futures::task::{Context, Poll};
+
+use crate::{CallbackFuture, Complete};
+
+/// Completion handle passed to the loader of a TryCallbackFuture.
+///
+/// Completes the future with either a value or an error.
+pub struct TryCompleter<T, E> {
+    complete: Complete<Result<T, E>>,
+}
+
+impl<T, E> TryCompleter<T, E> {
+    /// Completes the future with `Ok(value)`
+    pub fn complete_ok(self, value: T) {
+        (self.complete)(Ok(value))
+    }
+
+    /// Completes the future with `Err(error)`
+    pub fn complete_err(self, error: E) {
+        (self.complete)(Err(error))
+    }
+
+    /// Completes the future with the given result
+    pub fn complete_with(self, result: Result<T, E>) {
+        (self.complete)(result)
+    }
+}
+
+/// A fallible adapter between callbacks and futures.
+///
+/// Same as `CallbackFuture<Result<T, E>>`, but the loader receives a `TryCompleter`
+/// with helpers for reporting success or failure. Implements `TryFuture`.
+pub struct TryCallbackFuture<T, E> {
+    inner: CallbackFuture<Result<T, E>>,
+}
+
+impl<T, E> TryCallbackFuture<T, E> {
+    /// Creates a new TryCallbackFuture
+    ///
+    /// # Examples
+    /// ```
+    /// use callback_future::TryCallbackFuture;
+    /// use futures::executor::block_on;
+    /// use std::thread;
+    ///
+    /// // wraps a Node-style `(err, value)` callback
+    /// fn read(callback: impl FnOnce(Option<String>, Option<u32>) + Send + 'static) {
+    ///     thread::spawn(move || callback(None, Some(42)));
+    /// }
+    ///
+    /// let future = TryCallbackFuture::new(|completer| {
+    ///     read(move |err, value| match err {
+    ///         Some(err) => completer.complete_err(err),
+    ///         None => completer.complete_ok(value.unwrap()),
+    ///     });
+    /// });
+    /// assert_eq!(block_on(future), Ok(42));
+    /// ```
+    pub fn new(loader: impl FnOnce(TryCompleter<T, E>) + Send + 'static) -> TryCallbackFuture<T, E> {
+        TryCallbackFuture {
+            inner: CallbackFuture::new(move |complete| loader(TryCompleter { complete })),
+        }
+    }
+
+    /// Creates a TryCallbackFuture which is ready with `Ok(value)`
+    pub fn ok(value: T) -> TryCallbackFuture<T, E> {
+        TryCallbackFuture { inner: CallbackFuture::ready(Ok(value)) }
+    }
+
+    /// Creates a TryCallbackFuture which is ready with `Err(error)`
+    pub fn err(error: E) -> TryCallbackFuture<T, E> {
+        TryCallbackFuture { inner: CallbackFuture::ready(Err(error)) }
+    }
+}
+
+impl<T, E> From<CallbackFuture<Result<T, E>>> for TryCallbackFuture<T, E> {
+    fn from(inner: CallbackFuture<Result<T, E>>) -> TryCallbackFuture<T, E> {
+        TryCallbackFuture { inner }
+    }
+}
+
+impl<T, E> From<TryCallbackFuture<T, E>> for CallbackFuture<Result<T, E>> {
+    fn from(future: TryCallbackFuture<T, E>) -> CallbackFuture<Result<T, E>> {
+        future.inner
+    }
+}
+
+impl<T: Send + 'static, E: Send + 'static> Future for TryCallbackFuture<T, E> {
+    type Output = Result<T, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.get_mut().inner).poll(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use futures::{executor::block_on, TryFutureExt};
+
+    use crate::{CallbackFuture, TryCallbackFuture};
+
+    #[test]
+    fn test_complete_ok() {
+        let fu = TryCallbackFuture::<_, ()>::new(move |completer| {
+            thread::spawn(move || { completer.complete_ok(42); });
+        });
+
+        assert_eq!(block_on(fu), Ok(42));
+    }
+
+    #[test]
+    fn test_complete_err() {
+        let fu = TryCallbackFuture::<(), _>::new(move |completer| {
+            thread::spawn(move || { completer.complete_err("error"); });
+        });
+
+        assert_eq!(block_on(fu), Err("error"));
+    }
+
+    #[test]
+    fn test_complete_with() {
+        let fu = TryCallbackFuture::new(move |completer| {
+            completer.complete_with("42".parse::<i32>());
+        });
+
+        assert_eq!(block_on(fu), Ok(42));
+    }
+
+    #[test]
+    fn test_ready() {
+        assert_eq!(block_on(TryCallbackFuture::<_, ()>::ok(42)), Ok(42));
+        assert_eq!(block_on(TryCallbackFuture::<(), _>::err("error")), Err("error"));
+    }
+
+    #[test]
+    fn test_try_future() {
+        let fu = TryCallbackFuture::<i32, &str>::new(move |completer| {
+            completer.complete_err("error");
+        });
+
+        assert_eq!(block_on(fu.map_err(str::len).and_then(|value| async move { Ok(value * 2) })),
+                   Err(5));
+    }
+
+    #[test]
+    fn test_from_callback_future() {
+        let fu: TryCallbackFuture<_, ()> = CallbackFuture::new(move |complete| {
+            complete(Ok(42));
+        }).into();
+
+        assert_eq!(block_on(fu), Ok(42));
+    }
+}
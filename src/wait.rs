@@ -0,0 +1,190 @@
+use std::pin::Pin;
+use std::sync::Arc;
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+use futures::Future;
+use futures::task::{ArcWake, Context, Poll, waker};
+
+use crate::{CallbackFuture, Load};
+
+/// Unparks the waiting thread upon completion.
+struct ThreadWaker(Thread);
+
+impl ArcWake for ThreadWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.0.unpark();
+    }
+}
+
+impl<T, L: Load<T>> CallbackFuture<T, L> {
+    /// Blocks the current thread until the callback fires, without any executor
+    ///
+    /// Invokes the loader if it was not yet invoked.
+    ///
+    /// # Examples
+    /// ```
+    /// use callback_future::CallbackFuture;
+    /// use std::thread;
+    ///
+    /// let future = CallbackFuture::new(|complete| {
+    ///     thread::spawn(move || complete("Test"));
+    /// });
+    /// assert_eq!(future.wait(), "Test");
+    /// ```
+    pub fn wait(mut self) -> T {
+        let waker = waker(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(value) = Pin::new(&mut self).poll(&mut cx) {
+                return value;
+            }
+            thread::park();
+        }
+    }
+
+    /// Blocks the current thread until the callback fires or the timeout elapses
+    ///
+    /// Returns the future back on timeout, so it can be waited for or awaited again.
+    ///
+    /// # Examples
+    /// ```
+    /// use callback_future::CallbackFuture;
+    /// use std::sync::mpsc;
+    /// use std::time::Duration;
+    ///
+    /// let (tx, rx) = mpsc::channel();
+    /// let future = CallbackFuture::new(move |complete| tx.send(complete).unwrap());
+    ///
+    /// let future = future.wait_timeout(Duration::from_millis(10)).unwrap_err();
+    /// rx.recv().unwrap()("Test");
+    /// assert_eq!(future.wait(), "Test");
+    /// ```
+    pub fn wait_timeout(mut self, timeout: Duration) -> Result<T, CallbackFuture<T, L>> {
+        let deadline = match Instant::now().checked_add(timeout) {
+            Some(deadline) => deadline,
+            // a deadline too far to represent is never reached
+            None => return Ok(self.wait()),
+        };
+        let waker = waker(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(value) = Pin::new(&mut self).poll(&mut cx) {
+                return Ok(value);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(self);
+            }
+            thread::park_timeout(deadline - now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use futures::executor::block_on;
+
+    use crate::CallbackFuture;
+
+    #[test]
+    fn test_wait_same_thread() {
+        let fu = CallbackFuture::new(move |complete| {
+            complete(42);
+        });
+
+        assert_eq!(fu.wait(), 42);
+    }
+
+    #[test]
+    fn test_wait_cross_thread() {
+        let fu = CallbackFuture::new(move |complete| {
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                complete(42);
+            });
+        });
+
+        assert_eq!(fu.wait(), 42);
+    }
+
+    #[test]
+    fn test_wait_ready() {
+        assert_eq!(CallbackFuture::ready(42).wait(), 42);
+    }
+
+    #[test]
+    fn test_wait_from_fn() {
+        let fu = CallbackFuture::from_fn(move |completer| {
+            thread::spawn(move || completer.complete(42));
+        });
+
+        assert_eq!(fu.wait(), 42);
+    }
+
+    #[test]
+    fn test_wait_inside_executor() {
+        let fu = CallbackFuture::new(move |complete| {
+            thread::spawn(move || { complete(42); });
+        });
+
+        assert_eq!(block_on(async { fu.wait() }), 42);
+    }
+
+    #[test]
+    fn test_wait_timeout_same_thread() {
+        let fu = CallbackFuture::new(move |complete| {
+            complete(42);
+        });
+
+        assert_eq!(fu.wait_timeout(Duration::from_secs(5)).ok(), Some(42));
+    }
+
+    #[test]
+    fn test_wait_timeout_cross_thread() {
+        let fu = CallbackFuture::new(move |complete| {
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                complete(42);
+            });
+        });
+
+        assert_eq!(fu.wait_timeout(Duration::from_secs(5)).ok(), Some(42));
+    }
+
+    #[test]
+    fn test_wait_timeout_elapsed() {
+        let fu = CallbackFuture::new(move |complete| {
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(200));
+                complete(42);
+            });
+        });
+
+        let start = Instant::now();
+        let fu = match fu.wait_timeout(Duration::from_millis(50)) {
+            Ok(_) => panic!("completed before timeout"),
+            Err(fu) => fu,
+        };
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        // loader is not invoked again
+        assert_eq!(block_on(fu), 42);
+    }
+
+    #[test]
+    fn test_wait_timeout_max() {
+        let fu = CallbackFuture::new(move |complete| {
+            complete(42);
+        });
+        assert_eq!(fu.wait_timeout(Duration::MAX).ok(), Some(42));
+
+        let fu = CallbackFuture::new(move |complete| {
+            thread::spawn(move || complete(42));
+        });
+        assert_eq!(fu.wait_timeout(Duration::MAX).ok(), Some(42));
+    }
+}
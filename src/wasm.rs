@@ -0,0 +1,146 @@
+//! Bridge between JS callbacks, promises and futures, enabled by the `wasm` feature.
+//!
+//! JS values are not `Send`, so everything here builds on `LocalCallbackFuture` and is meant
+//! for `wasm_bindgen_futures::spawn_local`.
+
+use std::cell::RefCell;
+use std::pin::Pin;
+use std::rc::{Rc, Weak};
+
+use futures::Future;
+use futures::task::{Context, Poll};
+use js_sys::{Function, Promise};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::future_to_promise;
+
+use crate::{CallbackFuture, LocalCallbackFuture, Load};
+
+/// Completion callback of a JsCallbackFuture, once the loader was invoked
+type Completion<T> = RefCell<Option<Box<dyn FnOnce(T)>>>;
+
+/// An adapter between JS callbacks and futures.
+///
+/// The loader receives a JS function to pass to a JS API as the callback. The function is owned
+/// by JS and stays valid until it is garbage collected, so it may be called at any time: calls
+/// after the first one, or after the future is dropped, e.g. when it lost a `select!`, are ignored.
+pub struct JsCallbackFuture<T> {
+    future: LocalCallbackFuture<T>,
+    // the only strong reference: JS functions complete the future only while it is alive
+    _completion: Rc<Completion<T>>,
+}
+
+impl JsCallbackFuture<JsValue> {
+    /// Creates a new JsCallbackFuture resolving to the first argument of the callback
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use callback_future::wasm::JsCallbackFuture;
+    /// use js_sys::Function;
+    /// use wasm_bindgen::prelude::*;
+    ///
+    /// #[wasm_bindgen]
+    /// extern "C" {
+    ///     #[wasm_bindgen(js_name = setTimeout)]
+    ///     fn set_timeout(callback: &Function, millis: u32) -> JsValue;
+    /// }
+    ///
+    /// async fn sleep(millis: u32) {
+    ///     JsCallbackFuture::new(move |callback| {
+    ///         set_timeout(callback, millis);
+    ///     }).await;
+    /// }
+    /// ```
+    pub fn new(loader: impl FnOnce(&Function) + 'static) -> JsCallbackFuture<JsValue> {
+        let completion = Rc::new(Completion::default());
+        let weak = Rc::downgrade(&completion);
+        JsCallbackFuture {
+            future: LocalCallbackFuture::new(move |complete| {
+                set_completion(&weak, complete);
+                let callback: Function = closure(weak, |value| value).into_js_value().unchecked_into();
+                loader(&callback);
+            }),
+            _completion: completion,
+        }
+    }
+}
+
+impl<T> JsCallbackFuture<T> {
+    /// Returns `true` if the callback fired
+    pub fn is_completed(&self) -> bool {
+        self.future.is_completed()
+    }
+}
+
+/// Resolves to the fulfillment value of the promise, or to its rejection reason as an error.
+impl From<Promise> for JsCallbackFuture<Result<JsValue, JsValue>> {
+    fn from(promise: Promise) -> JsCallbackFuture<Result<JsValue, JsValue>> {
+        let completion = Rc::new(Completion::default());
+        let weak = Rc::downgrade(&completion);
+        JsCallbackFuture {
+            future: LocalCallbackFuture::new(move |complete| {
+                set_completion(&weak, complete);
+                // either of the handlers completes the future
+                let resolve = closure(weak.clone(), Ok);
+                let reject = closure(weak, Err);
+                let _ = promise.then2(&resolve, &reject);
+                // owned by the promise from now on
+                resolve.into_js_value();
+                reject.into_js_value();
+            }),
+            _completion: completion,
+        }
+    }
+}
+
+/// Drives the future on the `spawn_local` executor; the promise is fulfilled with `Ok`
+/// and rejected with `Err`.
+impl From<JsCallbackFuture<Result<JsValue, JsValue>>> for Promise {
+    fn from(future: JsCallbackFuture<Result<JsValue, JsValue>>) -> Promise {
+        future_to_promise(future)
+    }
+}
+
+/// Drives the future on the `spawn_local` executor; the promise is fulfilled with `Ok`
+/// and rejected with `Err`.
+impl From<LocalCallbackFuture<Result<JsValue, JsValue>>> for Promise {
+    fn from(future: LocalCallbackFuture<Result<JsValue, JsValue>>) -> Promise {
+        future_to_promise(future)
+    }
+}
+
+/// Drives the future on the `spawn_local` executor; the promise is fulfilled with `Ok`
+/// and rejected with `Err`.
+impl<L: Load<Result<JsValue, JsValue>> + 'static> From<CallbackFuture<Result<JsValue, JsValue>, L>> for Promise {
+    fn from(future: CallbackFuture<Result<JsValue, JsValue>, L>) -> Promise {
+        future_to_promise(future)
+    }
+}
+
+/// Stores the completion callback in the future, which is alive while its loader runs
+fn set_completion<T>(completion: &Weak<Completion<T>>, complete: Box<dyn FnOnce(T)>) {
+    if let Some(completion) = completion.upgrade() {
+        *completion.borrow_mut() = Some(complete);
+    }
+}
+
+/// Creates a closure callable from JS which completes the future with the mapped argument,
+/// ignoring calls after the first and calls after the future is dropped
+fn closure<T: 'static>(completion: Weak<Completion<T>>, map: fn(JsValue) -> T)
+                       -> Closure<dyn FnMut(JsValue)> {
+    Closure::new(move |value| {
+        // not borrowed during the call, which may drop the future
+        let complete = completion.upgrade().and_then(|completion| completion.borrow_mut().take());
+        if let Some(complete) = complete {
+            complete(map(value));
+        }
+    })
+}
+
+impl<T: 'static> Future for JsCallbackFuture<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.get_mut().future).poll(cx)
+    }
+}
@@ -0,0 +1,128 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use callback_future::async_io::{unblock, AsyncIoTimer};
+use callback_future::{retry_on, CallbackFuture, Canceled, Elapsed, RetryPolicy};
+
+/// Runs each test on smol, on async-std and on the executor from `futures`.
+macro_rules! on_every_executor {
+    ($($test:ident),* $(,)?) => {
+        mod smol_executor {
+            $(
+                #[test]
+                fn $test() {
+                    smol::block_on(super::$test())
+                }
+            )*
+        }
+
+        mod async_std_executor {
+            $(
+                #[async_std::test]
+                async fn $test() {
+                    super::$test().await
+                }
+            )*
+        }
+
+        mod futures_executor {
+            $(
+                #[test]
+                fn $test() {
+                    futures::executor::block_on(super::$test())
+                }
+            )*
+        }
+    };
+}
+
+on_every_executor!(
+    test_unblock,
+    test_unblock_panic,
+    test_unblock_concurrent,
+    test_timeout_in_time,
+    test_timeout_elapsed,
+    test_cancel_on_elapsed,
+    test_retry,
+);
+
+async fn test_unblock() {
+    let fu = unblock(|| {
+        thread::sleep(Duration::from_millis(10));
+        42
+    });
+
+    assert_eq!(fu.await, Ok(42));
+}
+
+async fn test_unblock_panic() {
+    let fu = unblock(|| -> i32 { panic!("blocking function failed") });
+
+    assert_eq!(fu.await, Err(Canceled));
+}
+
+async fn test_unblock_concurrent() {
+    let start = Instant::now();
+    let fus = (0..4).map(|i| unblock(move || {
+        thread::sleep(Duration::from_millis(100));
+        i
+    }));
+
+    let results = futures::future::join_all(fus).await;
+    assert_eq!(results, vec![Ok(0), Ok(1), Ok(2), Ok(3)]);
+    // the executor thread is not blocked
+    assert!(start.elapsed() < Duration::from_millis(400));
+}
+
+async fn test_timeout_in_time() {
+    let fu = CallbackFuture::new(move |complete| {
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            complete(42);
+        });
+    });
+
+    assert_eq!(fu.with_timeout_on(&AsyncIoTimer, Duration::from_secs(5)).await, Ok(42));
+}
+
+async fn test_timeout_elapsed() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let fu = CallbackFuture::new(move |complete| {
+        tx.send(complete).unwrap();
+    });
+
+    let start = Instant::now();
+    assert_eq!(fu.with_timeout_on(&AsyncIoTimer, Duration::from_millis(50)).await, Err(Elapsed));
+    assert!(start.elapsed() >= Duration::from_millis(50));
+
+    // late callback is ignored
+    rx.recv().unwrap()(42);
+}
+
+async fn test_cancel_on_elapsed() {
+    let canceled = Arc::new(AtomicUsize::new(0));
+    let request_canceled = canceled.clone();
+    let fu = CallbackFuture::<i32>::with_cancel(move |_complete| {
+        move || { request_canceled.fetch_add(1, Ordering::SeqCst); }
+    });
+
+    assert_eq!(fu.with_timeout_on(&AsyncIoTimer, Duration::from_millis(10)).await, Err(Elapsed));
+    assert_eq!(canceled.load(Ordering::SeqCst), 1);
+}
+
+async fn test_retry() {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let loader_attempts = attempts.clone();
+    let fu = retry_on(AsyncIoTimer, move |completer| {
+        let attempt = loader_attempts.fetch_add(1, Ordering::SeqCst);
+        thread::spawn(move || match attempt {
+            0 | 1 => completer.complete_err("error"),
+            _ => completer.complete_ok(attempt),
+        });
+    }, RetryPolicy::fixed(Duration::from_millis(10)));
+
+    assert_eq!(fu.await, Ok(2));
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+}
@@ -0,0 +1,90 @@
+//! Kept apart from the unit tests, as the counting allocator replaces the global allocator
+//! of the whole test binary.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::mpsc;
+use std::thread;
+
+use futures::executor::block_on;
+use futures::poll;
+
+use callback_future::{CallbackFuture, Completion};
+
+/// Counts allocations and deallocations made by the current thread.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    static DEALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let _ = DEALLOCATIONS.try_with(|deallocations| deallocations.set(deallocations.get() + 1));
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Returns the numbers of allocations and deallocations made by the current thread so far
+fn allocations() -> (usize, usize) {
+    (ALLOCATIONS.with(Cell::get), DEALLOCATIONS.with(Cell::get))
+}
+
+/// Returns the numbers of allocations and deallocations made by `f`
+fn count_allocations(f: impl FnOnce()) -> (usize, usize) {
+    let before = allocations();
+    f();
+    let after = allocations();
+    (after.0 - before.0, after.1 - before.1)
+}
+
+#[test]
+fn test_callback_does_not_allocate() {
+    let (dispatcher, completions) = mpsc::sync_channel::<Completion>(1);
+    let (tx, rx) = mpsc::channel();
+    let mut fu = CallbackFuture::with_executor(dispatcher, move |complete| {
+        tx.send(complete).unwrap();
+    });
+    assert!(block_on(async { poll!(&mut fu) }).is_pending());
+
+    let complete = rx.recv().unwrap();
+    let callback_allocations = thread::spawn(move || {
+        let value = vec![42];
+        count_allocations(move || complete(value))
+    }).join().unwrap();
+
+    // the boxed callback is freed when called
+    assert_eq!(callback_allocations, (0, 1));
+    completions.recv().unwrap().run();
+    assert_eq!(block_on(fu), vec![42]);
+}
+
+#[test]
+fn test_completer_does_not_allocate_or_free() {
+    let (dispatcher, completions) = mpsc::sync_channel::<Completion>(1);
+    let (tx, rx) = mpsc::channel();
+    let mut fu = CallbackFuture::from_fn_with_executor(dispatcher.clone(), move |completer| {
+        tx.send(completer).unwrap();
+    });
+    assert!(block_on(async { poll!(&mut fu) }).is_pending());
+
+    let completer = rx.recv().unwrap();
+    let callback_allocations = thread::spawn(move || {
+        let value = vec![42];
+        count_allocations(move || completer.complete(value))
+    }).join().unwrap();
+
+    assert_eq!(callback_allocations, (0, 0));
+    completions.recv().unwrap().run();
+    assert_eq!(block_on(fu), vec![42]);
+    drop(dispatcher);
+}
@@ -0,0 +1,172 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tokio::sync::oneshot;
+
+use callback_future::tokio::{spawn_blocking, TokioTimer};
+use callback_future::{retry_on, CallbackFuture, Canceled, Elapsed, RetryPolicy};
+
+/// Runs each test on both the current-thread and the multi-thread runtime.
+macro_rules! on_both_runtimes {
+    ($($test:ident),* $(,)?) => {
+        mod current_thread {
+            $(
+                #[tokio::test(flavor = "current_thread")]
+                async fn $test() {
+                    super::$test().await
+                }
+            )*
+        }
+
+        mod multi_thread {
+            $(
+                #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+                async fn $test() {
+                    super::$test().await
+                }
+            )*
+        }
+    };
+}
+
+on_both_runtimes!(
+    test_spawn_blocking,
+    test_spawn_blocking_panic,
+    test_spawn_blocking_concurrent,
+    test_into_oneshot,
+    test_into_oneshot_cancel,
+    test_from_oneshot,
+    test_from_oneshot_sender_dropped,
+    test_from_oneshot_cancel,
+    test_timeout_in_time,
+    test_timeout_elapsed,
+    test_retry,
+);
+
+async fn test_spawn_blocking() {
+    let fu = spawn_blocking(|| {
+        thread::sleep(Duration::from_millis(10));
+        42
+    });
+
+    assert_eq!(fu.await, Ok(42));
+}
+
+async fn test_spawn_blocking_panic() {
+    let fu = spawn_blocking(|| -> i32 { panic!("blocking function failed") });
+
+    assert_eq!(fu.await, Err(Canceled));
+}
+
+async fn test_spawn_blocking_concurrent() {
+    let start = Instant::now();
+    let fus = (0..4).map(|i| spawn_blocking(move || {
+        thread::sleep(Duration::from_millis(100));
+        i
+    }));
+
+    let results = futures::future::join_all(fus).await;
+    assert_eq!(results, vec![Ok(0), Ok(1), Ok(2), Ok(3)]);
+    // the runtime threads are not blocked
+    assert!(start.elapsed() < Duration::from_millis(400));
+}
+
+async fn test_into_oneshot() {
+    let receiver = CallbackFuture::new(move |complete| {
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            complete(42);
+        });
+    }).into_oneshot();
+
+    assert_eq!(receiver.await, Ok(42));
+}
+
+async fn test_into_oneshot_cancel() {
+    let canceled = Arc::new(AtomicUsize::new(0));
+    let request_canceled = canceled.clone();
+    let (started_tx, started_rx) = oneshot::channel();
+    let receiver = CallbackFuture::<i32>::with_cancel(move |_complete| {
+        started_tx.send(()).unwrap();
+        move || { request_canceled.fetch_add(1, Ordering::SeqCst); }
+    }).into_oneshot();
+
+    started_rx.await.unwrap();
+    drop(receiver);
+    while canceled.load(Ordering::SeqCst) == 0 {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    assert_eq!(canceled.load(Ordering::SeqCst), 1);
+}
+
+async fn test_from_oneshot() {
+    let (sender, receiver) = oneshot::channel();
+    let fu = CallbackFuture::from(receiver);
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(10));
+        sender.send(42).unwrap();
+    });
+
+    assert_eq!(fu.await, Ok(42));
+}
+
+async fn test_from_oneshot_sender_dropped() {
+    let (sender, receiver) = oneshot::channel::<i32>();
+    let fu = CallbackFuture::from(receiver);
+    drop(sender);
+
+    assert_eq!(fu.await, Err(Canceled));
+}
+
+async fn test_from_oneshot_cancel() {
+    let (mut sender, receiver) = oneshot::channel::<i32>();
+    let fu = CallbackFuture::from(receiver);
+
+    assert_eq!(fu.with_timeout_on(&TokioTimer, Duration::from_millis(10)).await, Err(Elapsed));
+    // the receiver is dropped with the aborted task
+    sender.closed().await;
+}
+
+async fn test_timeout_in_time() {
+    let fu = CallbackFuture::new(move |complete| {
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            complete(42);
+        });
+    });
+
+    assert_eq!(fu.with_timeout_on(&TokioTimer, Duration::from_secs(5)).await, Ok(42));
+}
+
+async fn test_timeout_elapsed() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let fu = CallbackFuture::new(move |complete| {
+        tx.send(complete).unwrap();
+    });
+
+    let start = Instant::now();
+    assert_eq!(fu.with_timeout_on(&TokioTimer, Duration::from_millis(50)).await, Err(Elapsed));
+    assert!(start.elapsed() >= Duration::from_millis(50));
+
+    // late callback is ignored
+    rx.recv().unwrap()(42);
+}
+
+async fn test_retry() {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let loader_attempts = attempts.clone();
+    let fu = retry_on(TokioTimer, move |completer| {
+        let attempt = loader_attempts.fetch_add(1, Ordering::SeqCst);
+        tokio::spawn(async move {
+            match attempt {
+                0 | 1 => completer.complete_err("error"),
+                _ => completer.complete_ok(attempt),
+            }
+        });
+    }, RetryPolicy::fixed(Duration::from_millis(10)));
+
+    assert_eq!(fu.await, Ok(2));
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+}
@@ -0,0 +1,161 @@
+//! Run with `cargo test --target wasm32-unknown-unknown --features wasm --test wasm`,
+//! which requires `wasm-bindgen-test-runner` from `wasm-bindgen-cli` and Node.
+
+#![cfg(target_arch = "wasm32")]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use futures::poll;
+use js_sys::{Function, Promise};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+use callback_future::wasm::JsCallbackFuture;
+use callback_future::{CallbackFuture, Completer, LocalCallbackFuture};
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_name = setTimeout)]
+    fn set_timeout(callback: &Function, millis: u32) -> JsValue;
+}
+
+async fn sleep(millis: u32) {
+    JsCallbackFuture::new(move |callback| {
+        set_timeout(callback, millis);
+    }).await;
+}
+
+#[wasm_bindgen_test]
+async fn test_set_timeout() {
+    let fu = JsCallbackFuture::new(|callback| {
+        set_timeout(callback, 10);
+    });
+
+    assert!(fu.await.is_undefined());
+}
+
+#[wasm_bindgen_test]
+async fn test_callback_argument() {
+    let fu = JsCallbackFuture::new(|callback| {
+        let callback = callback.clone();
+        // JS code calling back later with a value
+        let later = Closure::once_into_js(move || {
+            callback.call1(&JsValue::NULL, &JsValue::from(42)).unwrap();
+        });
+        set_timeout(later.unchecked_ref(), 10);
+    });
+
+    assert_eq!(fu.await, 42);
+}
+
+#[wasm_bindgen_test]
+async fn test_callback_sync() {
+    let fu = JsCallbackFuture::new(|callback| {
+        callback.call1(&JsValue::NULL, &JsValue::from("Test")).unwrap();
+    });
+
+    assert_eq!(fu.await, "Test");
+}
+
+#[wasm_bindgen_test]
+async fn test_second_call_ignored() {
+    let fu = JsCallbackFuture::new(|callback| {
+        callback.call1(&JsValue::NULL, &JsValue::from(1)).unwrap();
+        callback.call1(&JsValue::NULL, &JsValue::from(2)).unwrap();
+    });
+
+    assert_eq!(fu.await, 1);
+}
+
+#[wasm_bindgen_test]
+async fn test_callback_after_drop() {
+    let function = Rc::new(RefCell::new(None));
+    let loader_function = function.clone();
+    let mut fu = JsCallbackFuture::new(move |callback| {
+        *loader_function.borrow_mut() = Some(callback.clone());
+    });
+
+    assert!(poll!(&mut fu).is_pending());
+    drop(fu);
+    // the function stays valid, and the call is ignored
+    let function = function.borrow_mut().take().unwrap();
+    assert!(function.call1(&JsValue::NULL, &JsValue::from(42)).is_ok());
+    assert!(function.call1(&JsValue::NULL, &JsValue::from(42)).is_ok());
+}
+
+#[wasm_bindgen_test]
+async fn test_from_promise_dropped() {
+    let resolve = Rc::new(RefCell::new(None));
+    let promise_resolve = resolve.clone();
+    let promise = Promise::new(&mut |resolve, _reject| *promise_resolve.borrow_mut() = Some(resolve));
+    let mut fu = JsCallbackFuture::from(promise.clone());
+
+    assert!(poll!(&mut fu).is_pending());
+    drop(fu);
+    let resolve = resolve.borrow_mut().take().unwrap();
+    resolve.call1(&JsValue::NULL, &JsValue::from(42)).unwrap();
+    // the handlers run without throwing, which would reject the promise returned by `then`
+    assert_eq!(JsFuture::from(promise).await, Ok(JsValue::from(42)));
+    sleep(10).await;
+}
+
+#[wasm_bindgen_test]
+async fn test_from_promise_resolved() {
+    let fu = JsCallbackFuture::from(Promise::resolve(&JsValue::from(42)));
+
+    assert_eq!(fu.await, Ok(JsValue::from(42)));
+}
+
+#[wasm_bindgen_test]
+async fn test_from_promise_rejected() {
+    let fu = JsCallbackFuture::from(Promise::reject(&JsValue::from("error")));
+
+    assert_eq!(fu.await, Err(JsValue::from("error")));
+}
+
+#[wasm_bindgen_test]
+async fn test_from_promise_later() {
+    let promise = Promise::new(&mut |resolve, _reject| {
+        let later = Closure::once_into_js(move || {
+            resolve.call1(&JsValue::NULL, &JsValue::from(42)).unwrap();
+        });
+        set_timeout(later.unchecked_ref(), 10);
+    });
+
+    assert_eq!(JsCallbackFuture::from(promise).await, Ok(JsValue::from(42)));
+}
+
+#[wasm_bindgen_test]
+async fn test_into_promise() {
+    let fu = LocalCallbackFuture::new(|complete| {
+        let later = Closure::once_into_js(move || complete(Ok(JsValue::from(42))));
+        set_timeout(later.unchecked_ref(), 10);
+    });
+
+    assert_eq!(JsFuture::from(Promise::from(fu)).await, Ok(JsValue::from(42)));
+}
+
+#[wasm_bindgen_test]
+async fn test_promise_round_trip() {
+    let fu = JsCallbackFuture::from(Promise::resolve(&JsValue::from(42)));
+
+    assert_eq!(JsFuture::from(Promise::from(fu)).await, Ok(JsValue::from(42)));
+}
+
+#[wasm_bindgen_test]
+async fn test_into_promise_rejected() {
+    let fu = LocalCallbackFuture::new(|complete| complete(Err(JsValue::from("error"))));
+
+    assert_eq!(JsFuture::from(Promise::from(fu)).await, Err(JsValue::from("error")));
+}
+
+#[wasm_bindgen_test]
+async fn test_callback_future_into_promise() {
+    let fu = CallbackFuture::from_fn(|completer: Completer<Result<JsValue, JsValue>>| {
+        completer.complete(Ok(JsValue::from(42)));
+    });
+
+    assert_eq!(JsFuture::from(Promise::from(fu)).await, Ok(JsValue::from(42)));
+}